@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use try_rust_bevy::generator::{generate, GeneratorSettings};
+
+// シードと難しさからステージを作ってステージのファイルに書き出す
+// cargo run --bin generate_stage -- [--seed N] [--difficulty 0.0〜1.0] [--width W] [--height H] [--out 出力先]
+// 出力先を指定しなければ標準出力に書き出す
+fn main() -> ExitCode {
+    let mut settings = GeneratorSettings::default();
+    let mut out = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            eprintln!("{} needs a value", arg);
+            return ExitCode::FAILURE;
+        };
+        let parsed = match arg.as_str() {
+            "--seed" => value.parse().map(|seed| settings.seed = seed).is_ok(),
+            "--difficulty" => value
+                .parse()
+                .map(|difficulty| settings.difficulty = difficulty)
+                .is_ok(),
+            "--width" => value.parse().map(|width| settings.width = width).is_ok(),
+            "--height" => value.parse().map(|height| settings.height = height).is_ok(),
+            "--out" => {
+                out = Some(PathBuf::from(&value));
+                true
+            }
+            _ => {
+                eprintln!("unknown option {}", arg);
+                return ExitCode::FAILURE;
+            }
+        };
+        if !parsed {
+            eprintln!("{} has invalid value '{}'", arg, value);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(stage) = generate(&settings) else {
+        eprintln!("could not generate a completable stage from {:?}", settings);
+        return ExitCode::FAILURE;
+    };
+    let ron = match stage.to_ron() {
+        Ok(ron) => ron,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    match out {
+        Some(path) => match std::fs::write(&path, ron) {
+            Ok(()) => println!("{}", path.display()),
+            Err(error) => {
+                eprintln!("{}: {}", path.display(), error);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => println!("{}", ron),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use try_rust_bevy::campaign::Campaign;
+use try_rust_bevy::consts::{ASSETS_DIR, MAIN_CAMPAIGN};
+use try_rust_bevy::preview::render_stage;
+use try_rust_bevy::tiled::load_stage_at;
+
+// ステージ全体のプレビュー画像(PNG)を書き出す
+// cargo run --bin render_stage -- [--out 出力先] [ステージのファイル...]
+// ファイルを指定しなければキャンペーン(assets/stages/main.campaign.ron)の全ステージを書き出す
+const DEFAULT_OUT: &str = "previews";
+
+fn main() -> ExitCode {
+    let mut out = PathBuf::from(DEFAULT_OUT);
+    let mut paths = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--out" {
+            let Some(dir) = args.next() else {
+                eprintln!("--out needs a directory");
+                return ExitCode::FAILURE;
+            };
+            out = PathBuf::from(dir);
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() {
+        match Campaign::stage_paths(Path::new(ASSETS_DIR)) {
+            Ok(stages) => paths = stages,
+            Err(error) => {
+                eprintln!("{}: {}", MAIN_CAMPAIGN, error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Err(error) = std::fs::create_dir_all(&out) {
+        eprintln!("{}: {}", out.display(), error);
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = false;
+    for path in paths.iter() {
+        let output = out.join(preview_name(path));
+        match render(path, &output) {
+            Ok(()) => println!("{} -> {}", path.display(), output.display()),
+            Err(error) => {
+                failed = true;
+                eprintln!("{}: {}", path.display(), error);
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn render(path: &Path, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let stage = load_stage_at(path)?;
+    render_stage(&stage, Path::new(ASSETS_DIR))?.save(output)?;
+    Ok(())
+}
+
+// stage1.stage.ron -> stage1.stage.png、stage1.tmx -> stage1.png
+// 最後の拡張子だけを替えるので、同じ名前のステージ定義ファイルとTiledのマップが重ならない
+fn preview_name(path: &Path) -> PathBuf {
+    PathBuf::from(path.file_name().unwrap_or("stage".as_ref())).with_extension("png")
+}
@@ -0,0 +1,44 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use try_rust_bevy::campaign::Campaign;
+use try_rust_bevy::consts::{ASSETS_DIR, MAIN_CAMPAIGN};
+use try_rust_bevy::validation::validate_stage_at;
+
+// ステージデータを検査する
+// cargo run --bin validate_stage [ステージのファイル...]
+// ファイルを指定しなければキャンペーン(assets/stages/main.campaign.ron)の全ステージを検査する
+fn main() -> ExitCode {
+    let paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    let paths = if paths.is_empty() {
+        match Campaign::stage_paths(Path::new(ASSETS_DIR)) {
+            Ok(paths) => paths,
+            Err(error) => {
+                eprintln!("{}: {}", MAIN_CAMPAIGN, error);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        paths
+    };
+
+    let mut failed = false;
+    for path in paths.iter() {
+        let issues = validate_stage_at(path);
+        if issues.is_empty() {
+            println!("{}: ok", path.display());
+        } else {
+            failed = true;
+            println!("{}:", path.display());
+            for issue in issues {
+                println!("  {}", issue);
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use crate::consts::MAIN_CAMPAIGN;
+use crate::movement::Abilities;
+use crate::stage::Ability;
+
+// ステージの並び順を決めるキャンペーン定義(assets/stages/*.campaign.ron)を読み込むためのプラグイン
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Campaign>()
+            .init_asset_loader::<CampaignLoader>()
+            .init_resource::<CampaignProgress>();
+    }
+}
+
+// 起動時に読み込みを開始するキャンペーン
+#[derive(Resource)]
+pub struct CurrentCampaign {
+    pub handle: Handle<Campaign>,
+}
+
+// 何番目のステージをプレイ中か（エンディング後、ゲームオーバー後にリセットする）
+#[derive(Resource, Default)]
+pub struct CampaignProgress {
+    pub stage: usize,
+    // 残機（ステージをまたいで持ち越す）。0でデスするとゲームオーバー
+    pub lives: u32,
+    // ボス戦まで進んでいたらリスポーン位置をボス戦の手前にする
+    pub boss_checkpoint: bool,
+    // 最後に触れたチェックポイント（StageData::checkpointsの番号）
+    pub checkpoint: Option<usize>,
+    // 倒した敵（StageData::enemiesの番号）
+    pub defeated_enemies: HashSet<usize>,
+    // 取った残機アイテム（StageData::extra_livesの番号）
+    pub extra_lives: HashSet<usize>,
+    // 使えるようになった能力（ステージをまたいで持ち越す）
+    pub abilities: Abilities,
+}
+
+impl CampaignProgress {
+    // 最初のステージから始める
+    pub fn new(campaign: &Campaign) -> Self {
+        CampaignProgress {
+            lives: campaign.lives,
+            ..default()
+        }
+    }
+
+    // コンティニュー。残機を戻して今のステージを最初からやり直す（能力はそのまま）
+    pub fn restart_stage(&mut self, campaign: &Campaign) {
+        *self = CampaignProgress {
+            stage: self.stage,
+            abilities: self.abilities,
+            ..CampaignProgress::new(campaign)
+        };
+    }
+
+    // 次のステージに進める。最後のステージだった場合はfalseを返す
+    pub fn next_stage(&mut self, campaign: &Campaign) -> bool {
+        self.boss_checkpoint = false;
+        self.checkpoint = None;
+        self.defeated_enemies.clear();
+        self.extra_lives.clear();
+        if self.stage + 1 < campaign.stages.len() {
+            self.stage += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum ClearCondition {
+    // ゴールに触れる（ゴールがなければ右端に着く）
+    Goal,
+    // ボスを倒す（ゴールではクリアにならない）
+    DefeatBoss,
+}
+
+// ボス戦はステージのトリガー(StartBoss)で始まる。ボスを倒すとクリア条件によらずステージクリア
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BossDefinition {
+    // 倒すと使えるようになる能力
+    #[serde(default)]
+    pub unlocks: Option<Ability>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CampaignStage {
+    pub title_card: String,
+    pub map: String,
+    #[serde(default)]
+    pub music: Option<String>,
+    pub clear: ClearCondition,
+    #[serde(default)]
+    pub boss: Option<BossDefinition>,
+    // デスしても倒した敵を復活させない
+    #[serde(default)]
+    pub keep_defeated_enemies: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, TypeUuid, TypePath)]
+#[uuid = "3f0c6a0e-5b7d-4e58-9d0b-2a41c7e8f6d3"]
+pub struct Campaign {
+    // プレイする順に並んでいる
+    pub stages: Vec<CampaignStage>,
+    // プレイヤーの体力。1なら敵に触れると即デス（一撃モード）
+    #[serde(default = "default_player_health")]
+    pub player_health: u32,
+    // 最初の残機
+    #[serde(default = "default_lives")]
+    pub lives: u32,
+}
+
+fn default_player_health() -> u32 {
+    1
+}
+
+fn default_lives() -> u32 {
+    3
+}
+
+#[derive(Debug)]
+pub enum CampaignLoadError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    NoStages,
+    MissingBoss(usize),
+    NoPlayerHealth,
+}
+
+impl fmt::Display for CampaignLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CampaignLoadError::Io(error) => write!(f, "{}", error),
+            CampaignLoadError::Parse(error) => write!(f, "invalid campaign file: {}", error),
+            CampaignLoadError::NoStages => write!(f, "campaign has no stages"),
+            CampaignLoadError::MissingBoss(index) => write!(
+                f,
+                "stage #{} is cleared by defeating the boss but has no boss",
+                index
+            ),
+            CampaignLoadError::NoPlayerHealth => write!(f, "player_health must be at least 1"),
+        }
+    }
+}
+
+impl std::error::Error for CampaignLoadError {}
+
+impl Campaign {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CampaignLoadError> {
+        let campaign: Campaign = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_bytes(bytes)
+            .map_err(CampaignLoadError::Parse)?;
+
+        if campaign.stages.is_empty() {
+            return Err(CampaignLoadError::NoStages);
+        }
+        if campaign.player_health == 0 {
+            return Err(CampaignLoadError::NoPlayerHealth);
+        }
+        for (i, stage) in campaign.stages.iter().enumerate() {
+            if stage.clear == ClearCondition::DefeatBoss && stage.boss.is_none() {
+                return Err(CampaignLoadError::MissingBoss(i));
+            }
+        }
+
+        Ok(campaign)
+    }
+
+    pub fn stage(&self, progress: &CampaignProgress) -> &CampaignStage {
+        &self.stages[progress.stage]
+    }
+
+    // ツール用。assetsにあるメインのキャンペーンを読んで、全ステージのファイルのパスを順に返す
+    pub fn stage_paths(assets: &Path) -> Result<Vec<PathBuf>, CampaignLoadError> {
+        let bytes = std::fs::read(assets.join(MAIN_CAMPAIGN)).map_err(CampaignLoadError::Io)?;
+        let campaign = Campaign::from_bytes(&bytes)?;
+        Ok(campaign
+            .stages
+            .iter()
+            .map(|stage| assets.join(&stage.map))
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let campaign = Campaign::from_bytes(bytes).map_err(|error| {
+                Error::msg(format!("{}: {}", load_context.path().display(), error))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(campaign));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campaign.ron"]
+    }
+}
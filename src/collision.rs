@@ -0,0 +1,599 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+
+use crate::stage::{StageData, TileKind};
+
+// タイルの種類をグリッドで持つ衝突判定用のマップ
+// 壁のエンティティを全部判定する代わりに、矩形が重なるタイルだけを調べる
+#[derive(Resource, Clone, Debug)]
+pub struct CollisionMap {
+    width: usize,
+    height: usize,
+    tile_size: f32,
+    // 下の行から順に並んでいる
+    tiles: Vec<TileKind>,
+}
+
+impl CollisionMap {
+    pub fn new(width: usize, height: usize, tile_size: f32) -> Self {
+        CollisionMap {
+            width,
+            height,
+            tile_size,
+            tiles: vec![TileKind::Background; width * height],
+        }
+    }
+
+    pub fn from_stage(stage: &StageData, tile_size: f32) -> Self {
+        let mut map = CollisionMap::new(stage.width(), stage.height(), tile_size);
+        for (column, row, tile) in stage.tiles() {
+            map.set_tile(column, row, tile.kind);
+        }
+        map
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // (列, 下から数えた行)のタイルを置き換える（ボス戦の壁など後から出現するもの用）
+    pub fn set_tile(&mut self, column: usize, row: usize, kind: TileKind) {
+        if column < self.width && row < self.height {
+            self.tiles[row * self.width + column] = kind;
+        }
+    }
+
+    // マップ外は背景扱い
+    pub fn tile(&self, column: i32, row: i32) -> TileKind {
+        if column < 0 || row < 0 {
+            return TileKind::Background;
+        }
+        let (column, row) = (column as usize, row as usize);
+        if column < self.width && row < self.height {
+            self.tiles[row * self.width + column]
+        } else {
+            TileKind::Background
+        }
+    }
+
+    pub fn is_solid(&self, column: i32, row: i32) -> bool {
+        self.tile(column, row) == TileKind::Wall
+    }
+
+    // 上からだけ乗れるタイル（足場とはしごの一番上）
+    pub fn is_platform(&self, column: i32, row: i32) -> bool {
+        match self.tile(column, row) {
+            TileKind::Platform => true,
+            TileKind::Ladder => self.tile(column, row + 1) != TileKind::Ladder,
+            _ => false,
+        }
+    }
+
+    // タイルの中心座標
+    pub fn tile_translation(&self, column: i32, row: i32) -> Vec3 {
+        Vec3::new(
+            self.tile_size * column as f32,
+            self.tile_size * row as f32,
+            0.,
+        )
+    }
+
+    // min..maxと重なるタイルの番号の範囲
+    // collide_aabbと同じく辺が接しているだけのタイルは含めない
+    fn tile_range(&self, min: f32, max: f32) -> RangeInclusive<i32> {
+        // タイルの中心が座標になっているので半タイル分ずらして数える
+        let half = self.tile_size / 2.;
+        let first = ((min - half) / self.tile_size).floor() as i32 + 1;
+        let last = ((max + half) / self.tile_size).ceil() as i32 - 1;
+        first..=last
+    }
+
+    // 矩形と重なっているタイルの(列, 行)を返す
+    pub fn overlapping_tiles(&self, center: Vec3, size: Vec2) -> impl Iterator<Item = (i32, i32)> {
+        let min = center.truncate() - size / 2.;
+        let max = center.truncate() + size / 2.;
+        let columns = self.tile_range(min.x, max.x);
+        self.tile_range(min.y, max.y)
+            .flat_map(move |row| columns.clone().map(move |column| (column, row)))
+    }
+
+    // 矩形と重なっている壁タイルの中心座標を返す
+    pub fn overlapping_walls(&self, center: Vec3, size: Vec2) -> impl Iterator<Item = Vec3> + '_ {
+        self.overlapping_tiles(center, size)
+            .filter(|&(column, row)| self.is_solid(column, row))
+            .map(|(column, row)| self.tile_translation(column, row))
+    }
+
+    pub fn overlaps_wall(&self, center: Vec3, size: Vec2) -> bool {
+        self.overlapping_walls(center, size).next().is_some()
+    }
+
+    // トゲなど触れるとデスになるタイルと重なっているか
+    pub fn overlaps_hazard(&self, center: Vec3, size: Vec2) -> bool {
+        self.overlapping_tiles(center, size)
+            .any(|(column, row)| self.tile(column, row) == TileKind::Spike)
+    }
+
+    // 矩形の中心の縦線に重なっているはしごの列を返す
+    pub fn ladder_column(&self, center: Vec3, size: Vec2) -> Option<i32> {
+        self.overlapping_tiles(center, Vec2::new(1., size.y))
+            .find(|&(column, row)| self.tile(column, row) == TileKind::Ladder)
+            .map(|(column, _)| column)
+    }
+
+    // 矩形の真下に接している壁か足場があるか
+    pub fn is_on_floor(&self, center: Vec3, size: Vec2) -> bool {
+        self.sweep(center.truncate(), size, Vec2::new(0., -1.))
+            .hit_floor
+    }
+
+    // 矩形の横に接している壁の向き（左が-1、右が1、どちらもなければ0）
+    pub fn wall_side(&self, center: Vec3, size: Vec2) -> f32 {
+        if self
+            .sweep(center.truncate(), size, Vec2::new(-1., 0.))
+            .hit_left
+        {
+            -1.
+        } else if self
+            .sweep(center.truncate(), size, Vec2::new(1., 0.))
+            .hit_right
+        {
+            1.
+        } else {
+            0.
+        }
+    }
+
+    // 矩形(中心position, 大きさsize)をdeltaだけ動かしたときの壁との衝突を解決する
+    // 横→縦の順に軸ごとに動かし、途中にある壁の手前で止める（速度が大きくてもすり抜けない）
+    // 足場は上から落ちてきたときだけ止まる
+    pub fn sweep(&self, position: Vec2, size: Vec2, delta: Vec2) -> Sweep {
+        self.sweep_with(position, size, delta, false)
+    }
+
+    // はしごを登り降りしている間は足場をすり抜ける
+    pub fn sweep_climbing(&self, position: Vec2, size: Vec2, delta: Vec2) -> Sweep {
+        self.sweep_with(position, size, delta, true)
+    }
+
+    fn sweep_with(
+        &self,
+        position: Vec2,
+        size: Vec2,
+        delta: Vec2,
+        through_platforms: bool,
+    ) -> Sweep {
+        let mut result = Sweep {
+            position,
+            ..default()
+        };
+        let half_size = size / 2.;
+
+        // 横方向
+        let rows = self.tile_range(position.y - half_size.y, position.y + half_size.y);
+        if let Some(edge) = self.sweep_axis(
+            position.x - half_size.x,
+            position.x + half_size.x,
+            delta.x,
+            |column| rows.clone().any(|row| self.is_solid(column, row)),
+        ) {
+            if delta.x > 0. {
+                result.position.x = edge - half_size.x;
+                result.hit_right = true;
+            } else {
+                result.position.x = edge + half_size.x;
+                result.hit_left = true;
+            }
+        } else {
+            result.position.x += delta.x;
+        }
+
+        // 縦方向（横移動後の位置で判定する）
+        let columns = self.tile_range(
+            result.position.x - half_size.x,
+            result.position.x + half_size.x,
+        );
+        // sweep_axisは進行方向の先にあるタイルしか見ないので、足場は下降中だけ含めれば上からの着地になる
+        let use_platforms = delta.y < 0. && !through_platforms;
+        if let Some(edge) = self.sweep_axis(
+            position.y - half_size.y,
+            position.y + half_size.y,
+            delta.y,
+            |row| {
+                columns.clone().any(|column| {
+                    self.is_solid(column, row) || (use_platforms && self.is_platform(column, row))
+                })
+            },
+        ) {
+            if delta.y > 0. {
+                result.position.y = edge - half_size.y;
+                result.hit_ceiling = true;
+            } else {
+                result.position.y = edge + half_size.y;
+                result.hit_floor = true;
+            }
+        } else {
+            result.position.y += delta.y;
+        }
+
+        result
+    }
+
+    // min..maxの区間をdeltaだけ動かしたときに最初にぶつかる壁の辺の座標を返す
+    // すでに重なっている壁は無視する（出現した壁に埋まっても抜け出せるように）
+    fn sweep_axis(
+        &self,
+        min: f32,
+        max: f32,
+        delta: f32,
+        is_solid: impl Fn(i32) -> bool,
+    ) -> Option<f32> {
+        let half = self.tile_size / 2.;
+        if delta > 0. {
+            // 左(下)の辺がmax以上、max + delta未満のタイル
+            let first = ((max - EPSILON + half) / self.tile_size).ceil() as i32;
+            let last = ((max + delta + half) / self.tile_size).ceil() as i32 - 1;
+            (first..=last)
+                .find(|&index| is_solid(index))
+                .map(|index| self.tile_size * index as f32 - half)
+        } else if delta < 0. {
+            // 右(上)の辺がmin以下、min + deltaより大きいタイル
+            let first = ((min + EPSILON - half) / self.tile_size).floor() as i32;
+            let last = ((min + delta - half) / self.tile_size).floor() as i32 + 1;
+            (last..=first)
+                .rev()
+                .find(|&index| is_solid(index))
+                .map(|index| self.tile_size * index as f32 + half)
+        } else {
+            None
+        }
+    }
+}
+
+// 誤差で接している壁を重なっていると判定しないための余裕
+const EPSILON: f32 = 0.001;
+
+// sweepの結果
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sweep {
+    // 衝突を解決した後の位置
+    pub position: Vec2,
+    pub hit_left: bool,
+    pub hit_right: bool,
+    // 着地した
+    pub hit_floor: bool,
+    // 天井に頭をぶつけた
+    pub hit_ceiling: bool,
+}
+
+impl Sweep {
+    pub fn hit(&self) -> bool {
+        self.hit_left || self.hit_right || self.hit_floor || self.hit_ceiling
+    }
+}
+
+// 上からだけ乗れる、タイルに沿わない矩形（動く足場や崩れる足場）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Support {
+    pub center: Vec2,
+    pub size: Vec2,
+    // このフレームで動いた量
+    pub delta: Vec2,
+}
+
+impl Support {
+    fn top(&self) -> f32 {
+        self.center.y + self.size.y / 2.
+    }
+
+    fn overlaps_horizontally(&self, center: Vec2, size: Vec2) -> bool {
+        (center.x - self.center.x).abs() < (size.x + self.size.x) / 2.
+    }
+
+    // 矩形(中心center, 大きさsize)がいまの位置の足場の上に立っているか
+    pub fn is_under(&self, center: Vec2, size: Vec2) -> bool {
+        self.overlaps_horizontally(center, size)
+            && (center.y - size.y / 2. - self.top()).abs() < STANDING_TOLERANCE
+    }
+
+    // 足場が動く前（前のフレーム）の位置で上に立っていたか
+    pub fn carries(&self, center: Vec2, size: Vec2) -> bool {
+        let previous = Support {
+            center: self.center - self.delta,
+            ..*self
+        };
+        previous.is_under(center, size)
+    }
+
+    // fromからtoに動いた矩形が上から着地するなら、着地したときの矩形の中心のyを返す
+    pub fn landing(&self, from: Vec2, to: Vec2, size: Vec2) -> Option<f32> {
+        let previous_top = self.top() - self.delta.y;
+        let from_bottom = from.y - size.y / 2.;
+        let to_bottom = to.y - size.y / 2.;
+        if self.overlaps_horizontally(to, size)
+            && from_bottom >= previous_top - STANDING_TOLERANCE
+            && to_bottom <= self.top()
+        {
+            Some(self.top() + size.y / 2.)
+        } else {
+            None
+        }
+    }
+}
+
+// 足場の上に立っているとみなす誤差
+const STANDING_TOLERANCE: f32 = 0.01;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 32.;
+    const PLAYER: Vec2 = Vec2::new(32., 32.);
+
+    // 幅10、高さ10で一番下の行だけが床のマップ
+    fn floor_map() -> CollisionMap {
+        let mut map = CollisionMap::new(10, 10, TILE);
+        for column in 0..10 {
+            map.set_tile(column, 0, TileKind::Wall);
+        }
+        map
+    }
+
+    #[test]
+    fn lands_on_floor_from_unaligned_height() {
+        let map = floor_map();
+        let sweep = map.sweep(Vec2::new(100., 70.3), PLAYER, Vec2::new(0., -50.));
+        assert!(sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(100., 32.));
+    }
+
+    #[test]
+    fn does_not_tunnel_through_floor_at_high_speed() {
+        let map = floor_map();
+        let sweep = map.sweep(Vec2::new(100., 300.), PLAYER, Vec2::new(0., -400.));
+        assert!(sweep.hit_floor);
+        assert_eq!(sweep.position.y, 32.);
+    }
+
+    #[test]
+    fn stands_still_on_floor() {
+        let map = floor_map();
+        assert!(map.is_on_floor(Vec3::new(100., 32., 0.), PLAYER));
+        assert!(!map.is_on_floor(Vec3::new(100., 33., 0.), PLAYER));
+    }
+
+    #[test]
+    fn bumps_head_on_ceiling() {
+        let mut map = floor_map();
+        map.set_tile(3, 5, TileKind::Wall);
+        let sweep = map.sweep(Vec2::new(100., 100.), PLAYER, Vec2::new(0., 30.));
+        assert!(sweep.hit_ceiling);
+        assert!(!sweep.hit_floor);
+        // 天井の下の辺は5 * 32 - 16 = 144
+        assert_eq!(sweep.position.y, 128.);
+    }
+
+    #[test]
+    fn jumping_into_wall_side_keeps_rising() {
+        let mut map = floor_map();
+        for row in 1..=4 {
+            map.set_tile(3, row, TileKind::Wall);
+        }
+        // 壁の左の辺は3 * 32 - 16 = 80
+        let sweep = map.sweep(Vec2::new(60., 50.), PLAYER, Vec2::new(8., 20.));
+        assert!(sweep.hit_right);
+        assert!(!sweep.hit_ceiling);
+        assert!(!sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(64., 70.));
+    }
+
+    #[test]
+    fn falling_along_wall_side_does_not_land() {
+        let mut map = floor_map();
+        for row in 1..=4 {
+            map.set_tile(3, row, TileKind::Wall);
+        }
+        let sweep = map.sweep(Vec2::new(64., 120.), PLAYER, Vec2::new(4., -10.));
+        assert!(sweep.hit_right);
+        assert!(!sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(64., 110.));
+    }
+
+    #[test]
+    fn finds_wall_beside() {
+        let mut map = floor_map();
+        for row in 1..=4 {
+            map.set_tile(3, row, TileKind::Wall);
+        }
+        assert_eq!(map.wall_side(Vec3::new(64., 100., 0.), PLAYER), 1.);
+        assert_eq!(map.wall_side(Vec3::new(128., 100., 0.), PLAYER), -1.);
+        assert_eq!(map.wall_side(Vec3::new(63., 100., 0.), PLAYER), 0.);
+    }
+
+    #[test]
+    fn jumping_into_wall_edge_is_blocked_sideways() {
+        let mut map = floor_map();
+        // 上端が80の1マスだけの壁
+        map.set_tile(3, 2, TileKind::Wall);
+        // 足元(74)が壁の上端よりわずかに下にある状態で右にジャンプ
+        let sweep = map.sweep(Vec2::new(60., 90.), PLAYER, Vec2::new(8., 10.));
+        assert!(sweep.hit_right);
+        assert!(!sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(64., 100.));
+    }
+
+    #[test]
+    fn passes_over_wall_edge_at_same_height() {
+        let mut map = floor_map();
+        map.set_tile(3, 2, TileKind::Wall);
+        // 足元が壁の上端とちょうど同じ高さなら引っかからずに乗れる
+        let sweep = map.sweep(Vec2::new(60., 96.), PLAYER, Vec2::new(8., -4.));
+        assert!(!sweep.hit_right);
+        assert!(sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(68., 96.));
+    }
+
+    #[test]
+    fn falling_past_wall_corner_diagonally() {
+        let mut map = floor_map();
+        map.set_tile(3, 2, TileKind::Wall);
+        // 横に動いてから落ちるので、角の横を通り過ぎて壁の上に着地する
+        let sweep = map.sweep(Vec2::new(60., 110.), PLAYER, Vec2::new(8., -20.));
+        assert!(!sweep.hit_right);
+        assert!(sweep.hit_floor);
+        assert_eq!(sweep.position, Vec2::new(68., 96.));
+    }
+
+    #[test]
+    fn moving_away_from_touching_wall() {
+        let mut map = floor_map();
+        map.set_tile(3, 1, TileKind::Wall);
+        let sweep = map.sweep(Vec2::new(64., 32.), PLAYER, Vec2::new(-4., 0.));
+        assert!(!sweep.hit());
+        assert_eq!(sweep.position, Vec2::new(60., 32.));
+    }
+
+    #[test]
+    fn stops_at_wall_from_left_side_moving_left() {
+        let mut map = floor_map();
+        map.set_tile(1, 1, TileKind::Wall);
+        // 壁の右の辺は1 * 32 + 16 = 48
+        let sweep = map.sweep(Vec2::new(70.5, 32.), PLAYER, Vec2::new(-10., 0.));
+        assert!(sweep.hit_left);
+        assert_eq!(sweep.position, Vec2::new(64., 32.));
+    }
+
+    #[test]
+    fn escapes_from_wall_it_is_buried_in() {
+        let mut map = floor_map();
+        map.set_tile(3, 1, TileKind::Wall);
+        let sweep = map.sweep(Vec2::new(96., 32.), PLAYER, Vec2::new(4., 0.));
+        assert!(!sweep.hit_right);
+        assert_eq!(sweep.position, Vec2::new(100., 32.));
+    }
+
+    #[test]
+    fn outside_of_map_is_empty() {
+        let map = floor_map();
+        let sweep = map.sweep(Vec2::new(0., 32.), PLAYER, Vec2::new(-100., 0.));
+        assert!(!sweep.hit());
+        assert_eq!(sweep.position, Vec2::new(-100., 32.));
+    }
+
+    #[test]
+    fn jumps_up_through_platform_and_lands_on_it() {
+        let mut map = floor_map();
+        map.set_tile(3, 3, TileKind::Platform);
+        // 下からは止まらずに通り抜ける
+        let sweep = map.sweep(Vec2::new(96., 64.), PLAYER, Vec2::new(0., 60.));
+        assert!(!sweep.hit());
+        assert_eq!(sweep.position.y, 124.);
+        // 上から落ちると足場の上端(3 * 32 + 16 = 112)に乗る
+        let sweep = map.sweep(Vec2::new(96., 140.), PLAYER, Vec2::new(0., -20.));
+        assert!(sweep.hit_floor);
+        assert_eq!(sweep.position.y, 128.);
+        assert!(map.is_on_floor(Vec3::new(96., 128., 0.), PLAYER));
+    }
+
+    #[test]
+    fn platform_does_not_catch_when_overlapping() {
+        let mut map = floor_map();
+        map.set_tile(3, 3, TileKind::Platform);
+        // ジャンプの途中で足場に重なっている状態から落ちても途中で止まらない
+        let sweep = map.sweep(Vec2::new(96., 110.), PLAYER, Vec2::new(0., -20.));
+        assert!(!sweep.hit_floor);
+        assert_eq!(sweep.position.y, 90.);
+    }
+
+    #[test]
+    fn climbing_passes_through_platforms() {
+        let mut map = floor_map();
+        map.set_tile(3, 3, TileKind::Platform);
+        let sweep = map.sweep_climbing(Vec2::new(96., 128.), PLAYER, Vec2::new(0., -2.));
+        assert!(!sweep.hit_floor);
+        assert_eq!(sweep.position.y, 126.);
+    }
+
+    #[test]
+    fn stands_on_top_of_ladder() {
+        let mut map = floor_map();
+        for row in 1..=3 {
+            map.set_tile(3, row, TileKind::Ladder);
+        }
+        // はしごの途中では乗れず、一番上だけ足場になる
+        assert!(!map.is_on_floor(Vec3::new(96., 96., 0.), PLAYER));
+        assert!(map.is_on_floor(Vec3::new(96., 128., 0.), PLAYER));
+        assert_eq!(map.ladder_column(Vec3::new(100., 64., 0.), PLAYER), Some(3));
+        assert_eq!(map.ladder_column(Vec3::new(96., 128., 0.), PLAYER), None);
+        assert_eq!(map.ladder_column(Vec3::new(96., 127., 0.), PLAYER), Some(3));
+    }
+
+    #[test]
+    fn touches_spike() {
+        let mut map = floor_map();
+        map.set_tile(3, 1, TileKind::Spike);
+        assert!(map.overlaps_hazard(Vec3::new(70., 32., 0.), PLAYER));
+        // 隣に立っているだけなら触れていない
+        assert!(!map.overlaps_hazard(Vec3::new(64., 32., 0.), PLAYER));
+        // トゲは床にはならない
+        assert!(!map.is_on_floor(Vec3::new(96., 64., 0.), PLAYER));
+    }
+
+    fn support() -> Support {
+        Support {
+            center: Vec2::new(100., 100.),
+            size: Vec2::new(64., 32.),
+            delta: Vec2::new(2., 0.),
+        }
+    }
+
+    #[test]
+    fn stands_on_support() {
+        let support = support();
+        assert!(support.is_under(Vec2::new(80., 132.), PLAYER));
+        assert!(!support.is_under(Vec2::new(80., 140.), PLAYER));
+        // 端が接しているだけなら乗っていない
+        assert!(!support.is_under(Vec2::new(148., 132.), PLAYER));
+        // 動く前の位置で乗っていたなら運ばれる
+        assert!(support.carries(Vec2::new(52., 132.), PLAYER));
+        assert!(!support.carries(Vec2::new(147., 132.), PLAYER));
+    }
+
+    #[test]
+    fn lands_on_support_from_above_only() {
+        let support = support();
+        assert_eq!(
+            support.landing(Vec2::new(100., 150.), Vec2::new(100., 120.), PLAYER),
+            Some(132.)
+        );
+        // 下から突き抜けるときは乗らない
+        assert_eq!(
+            support.landing(Vec2::new(100., 80.), Vec2::new(100., 120.), PLAYER),
+            None
+        );
+        // 横にずれていれば乗らない
+        assert_eq!(
+            support.landing(Vec2::new(200., 150.), Vec2::new(200., 120.), PLAYER),
+            None
+        );
+    }
+
+    #[test]
+    fn lands_on_rising_support() {
+        let support = Support {
+            delta: Vec2::new(0., 4.),
+            ..support()
+        };
+        // 足場が上がってきて、落ちてきた矩形の足元を追い越した
+        assert_eq!(
+            support.landing(Vec2::new(100., 130.), Vec2::new(100., 129.), PLAYER),
+            Some(132.)
+        );
+    }
+}
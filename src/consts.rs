@@ -9,14 +9,10 @@ pub enum GameState {
     StageTitle,
     Game,
     Ending,
-}
-
-#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
-pub enum StageState {
-    #[default]
-    Stage1,
-    Stage2,
-    Boss,
+    // 残機がなくなった（コンティニューかタイトルに戻るかを選ぶ）
+    GameOver,
+    // ステージエディタ（Gameはテストプレイに使う）
+    Editor,
 }
 
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
@@ -26,95 +22,28 @@ pub enum BossState {
     Active,
 }
 
-pub const STAGE1_MAP: [&str; 15] = [
-    "CAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACA",
-    "CAAAAAAABAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABACA",
-    "CAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAABAAAAAAAAACAAAAAAAAABAAAAAAACCCCCCCAAAAAAAAAAAAAAAACA",
-    "CAAAAAAAAAAAAAAAAAACACAAAAAAAAAAAAABAAAAAAAAAAAAAAAAAAAAACAAACCAAAAAAAAAAAAAAAAAAAAACCCAAACCCAAACCCA",
-    "CBAACCCCCAAAAAAAACCCACCCAAAAACAAAAAAAAAAAAAAAAAAAACCAAAAACCAAAAAAAAAACCCAAAAAAABAAAAAAAAAAAAAAAAAACA",
-    "CAAAAAAABAAAAAAAACCCAAAAAAAAACAAAAAAAAAAAAABAAAAAAAAAAAAACAAAAABAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACA",
-    "CACAAAAAAAAACCCCCCCCAABAAAACCCAAACAAAAAAAAAAAAACCAAAAAACACCCAAAAAAAAAAAAAAAAACCCAAAAAAAAAAABAAAAAACA",
-    "CACCAAAAAAAAAAAACCCCAAAAAAAAACAAABACABAAAAAAAAAACCAAAAACACAAAACCAAAACCCAAAAAAAAAAAAAAAAAAAAAAAAAAACA",
-    "CAACCAAAAAAAAAAACCCCAACCCAAAACAAAAAAACAAAAAAAAAAACCAAAACACAAAAAAAAAAAAAAAABAAAAAAABAAAAAAAAAAAAAAACA",
-    "AAAACCCAAAAAABAACCCCAAAAAAAAACAAAAAAABACAAAAAAAAAAAAACCCACAACAAABAAAAAACCCAAAAAAAAAAAAAAAAAAABAAAACA",
-    "AAAAAACCCAAAAAAACCCCAABAAAACCCAAAACAAAAAAAAAAABAAAAAAAACACAAAAAAAAAAAAAAAAAAACCCAAAAAAAAAAAAAAAAAACA",
-    "AAAAAAAACCCAAAAACCCCAAAACCAAACAAAAAAAAAACAAAAAACCCCCCCCCACCCCCAAAAAAAAAAAAAAAAAAAAAAAAAAABAAAAAAAAAA",
-    "AAAAAAAAAAAAAAAACCCCAAAAAAAAACAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAABAACAAAAAABAAAAAAAAAAAAAAAAAAAAAA",
-    "CCCCCCCCCCCCCCCCCCCCCCCAAAAAACCCCCCCCCCCCCAACCCCCCCCCCCCCCCCCCCAACAAAACAABACAAAACABAACCCCCCCCCCCCCCC",
-    "CCCCCCCCCCCCCCCCCCCCAAAAABAAACCCCCCCCCCCCCAACCCCCCCCCCCCCCCCCCCAACAAAACAAAACAAAACAAAACCCCCCCCCCCCCCC",
-];
-
-pub const STAGE2_MAP: [&str; 15] = [
-    "CAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAABAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAABAAAAAAAAAAA",
-    "CAAAAAAAAAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAACAAAAAAACCCCCCCCCCCCCCCCAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAACAAAAACAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAAAAAACAAAAAAAAAAACAACCCCCCCCCAACCCCCCCAACAAAAAAAAAAAAAAAAAAAAAACAAAAAAABAACAAAAAAAAAAAABAAAAAAAA",
-    "CAAAAAAAAAAAAAAAAAAAACAAAAAAAAAACCCCAAAAAAAACCCCCCCCCCCCCCCAAAAAAAACAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAACAAAABACAAABACAACAAAAAAAAAAACAAAAAAAAAACAAAAAAAAAAAAAAAACCAAAACAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAAAAAAAAAAAAAAAAAACAACCCCCCCAACAAACCCCCCCCAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAACAAAAAACCAAAACCAAAAAAA",
-    "CAAAAAACAAAAAAACAAAAACAAAAAAAAAAACCAAAAABAAAAAAAAAAAAAAAAAAAAAAAACACAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAA",
-    "CAAAAAAAABAACAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAAABCAAAAAAAAAACAAAAAABAAAAAAAAAAAAAA",
-    "CAAAAAAAAAAAAAAAAAAAACCCAAAAAAACCAACCAAAAAAAAAAAAAAAAAAAAAAAAAAACAACAAAAAACCAACAAACCAAAACCAAAACCAAAA",
-    "AAAAAACAAACAAAAACAAAACAAAACAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAAAAAACAAAAAAABAAAAAAAAAAAAAAAAAAAAAAAA",
-    "AAAAAAAAAAAAAAAAAAAAACAAAACCCCAAACCAAAACCAAAAAAAAAAAAACBAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
-    "CCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAAAAAAACAAAAACAAAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC",
-    "CCCCCCCCCCCCCCCCCCCCCCAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC",
-];
-
-#[derive(Clone, Copy, Debug)]
-pub struct EnemyPosition {
-    pub x: u8,
-    pub y: u8,
-}
-
-pub const STAGE1_ENEMY_POSITION: [EnemyPosition; 25] = [
-    EnemyPosition { x: 6, y: 8 },
-    EnemyPosition { x: 5, y: 3 },
-    EnemyPosition { x: 27, y: 3 },
-    EnemyPosition { x: 22, y: 3 },
-    EnemyPosition { x: 12, y: 12 },
-    EnemyPosition { x: 28, y: 9 },
-    EnemyPosition { x: 35, y: 3 },
-    EnemyPosition { x: 40, y: 12 },
-    EnemyPosition { x: 37, y: 12 },
-    EnemyPosition { x: 24, y: 7 },
-    EnemyPosition { x: 45, y: 2 },
-    EnemyPosition { x: 58, y: 10 },
-    EnemyPosition { x: 62, y: 2 },
-    EnemyPosition { x: 59, y: 12 },
-    EnemyPosition { x: 50, y: 7 },
-    EnemyPosition { x: 72, y: 8 },
-    EnemyPosition { x: 78, y: 9 },
-    EnemyPosition { x: 69, y: 3 },
-    EnemyPosition { x: 78, y: 5 },
-    EnemyPosition { x: 79, y: 1 },
-    EnemyPosition { x: 90, y: 12 },
-    EnemyPosition { x: 94, y: 12 },
-    EnemyPosition { x: 95, y: 2 },
-    EnemyPosition { x: 96, y: 12 },
-    EnemyPosition { x: 93, y: 12 },
-];
-
-pub const STAGE2_ENEMY_POSITION: [EnemyPosition; 20] = [
-    EnemyPosition { x: 6, y: 12 },
-    EnemyPosition { x: 14, y: 12 },
-    EnemyPosition { x: 23, y: 12 },
-    EnemyPosition { x: 16, y: 2 },
-    EnemyPosition { x: 12, y: 12 },
-    EnemyPosition { x: 28, y: 3 },
-    EnemyPosition { x: 35, y: 9 },
-    EnemyPosition { x: 40, y: 3 },
-    EnemyPosition { x: 37, y: 3 },
-    EnemyPosition { x: 24, y: 6 },
-    EnemyPosition { x: 45, y: 2 },
-    EnemyPosition { x: 58, y: 1 },
-    EnemyPosition { x: 62, y: 1 },
-    EnemyPosition { x: 59, y: 1 },
-    EnemyPosition { x: 50, y: 7 },
-    EnemyPosition { x: 72, y: 12 },
-    EnemyPosition { x: 78, y: 12 },
-    EnemyPosition { x: 69, y: 12 },
-    EnemyPosition { x: 74, y: 5 },
-    EnemyPosition { x: 79, y: 12 },
-];
+// ツールやエディタがファイルを直接読み書きするassetsの場所と、起動時に読み込むキャンペーン（assetsからのパス）
+pub const ASSETS_DIR: &str = "assets";
+pub const MAIN_CAMPAIGN: &str = "stages/main.campaign.ron";
+
+// ゲーム本体とステージの生成・検査で共有する値
+pub const TILE_SIZE: f32 = 32.;
+pub const CHARACTER_SIZE: f32 = 32.;
+pub const PLAYER_JUMP_FORCE: f32 = 44.;
+pub const PLAYER_JUMP_CUT: f32 = 0.4; // 上昇中にボタンを離したときに残す上昇の速さの割合（小ジャンプ）
+pub const PLAYER_COYOTE_FRAMES: usize = 6; // 足場から歩いて離れてもジャンプできるフレーム数
+pub const PLAYER_JUMP_BUFFER_FRAMES: usize = 6; // 着地の前に押したジャンプを受け付けるフレーム数
+pub const PLAYER_WALK_STEP: f32 = 4.; // 歩く速さの上限（1フレームの移動量）
+pub const PLAYER_WALK_ACCELERATION: f32 = 1.; // 1フレームで増やす横の速さ
+pub const PLAYER_WALK_FRICTION: f32 = 1.; // 入力を離したとき、逆に押したときに1フレームで減らす横の速さ
+pub const PLAYER_AIR_CONTROL: f32 = 0.5; // 空中での加速と減速の割合
+pub const PLAYER_STICK_DEAD_ZONE: f32 = 0.2; // スティックの傾きを無視する範囲
+pub const PLAYER_CLIMB_STEP: f32 = 2.;
+pub const PLAYER_AIR_JUMPS: u32 = 1; // 二段ジャンプで空中で跳べる回数
+pub const PLAYER_DASH_SPEED: f32 = 8.; // ダッシュ中の横の速さ（1フレームの移動量）
+pub const PLAYER_DASH_FRAMES: usize = 10;
+pub const PLAYER_DASH_COOLDOWN_FRAMES: usize = 20; // ダッシュが終わってから次にダッシュできるまでのフレーム数
+pub const PLAYER_WALL_SLIDE_SPEED: f32 = 2.; // 壁ずり落ち中の落ちる速さの上限（1フレームの移動量）
+pub const PLAYER_WALL_JUMP_SPEED: f32 = 6.; // 壁ジャンプで壁から離れる横の速さ
+pub const GRAVITY: f32 = 9.81;
+pub const GRAVITY_TIME_STEP: f32 = 0.24; // FPS通りだと重力加速が少ないので経過時間を補正
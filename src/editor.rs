@@ -0,0 +1,618 @@
+use try_rust_bevy::campaign::*;
+use try_rust_bevy::consts::*;
+use try_rust_bevy::stage::*;
+use try_rust_bevy::utils::*;
+
+// ステージのタイル、敵、プレイヤーの出現位置を編集してテストプレイ・保存するためのScene
+//   矢印キー/マウス: カーソル移動
+//   Tab/数字キー: ブラシ（タイル、敵、プレイヤーの出現位置）の切り替え
+//   Space/左クリック: ブラシで塗る
+//   Delete/右クリック: カーソル位置の敵を消す
+//   F: カーソル位置の敵の向きを変える
+//   Enter: テストプレイ（Escでエディタに戻る）
+//   Ctrl+S: ステージ定義ファイルに保存（Tiledのマップは保存できない）
+//   PageUp/PageDown: 前後のステージに切り替え（保存していない編集は消える）
+//   Esc: タイトルに戻る
+pub mod editor_scene {
+    use std::path::Path;
+
+    use bevy::prelude::*;
+    use bevy::window::PrimaryWindow;
+    use try_rust_bevy::tilemap::{chunk_of, tile_batches};
+    use try_rust_bevy::validation::validate_stage_data;
+
+    use super::{
+        despawn_screen, BossState, Campaign, CampaignProgress, CurrentCampaign, CurrentStage,
+        EnemyKind, EnemyPosition, Facing, GameState, StageData, TilePosition, ASSETS_DIR,
+        CHARACTER_SIZE, TILE_SIZE,
+    };
+    use crate::game::game_scene::{camera_bounds, spawn_enemy_character, spawn_tile_batch};
+
+    const CURSOR_COLOR: Color = Color::rgba(1., 1., 1., 0.3);
+    const BRUSH_COLOR: Color = Color::rgba(1., 1., 1., 0.7);
+
+    pub struct EditorPlugin;
+
+    impl Plugin for EditorPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Editor), editor_setup)
+                .add_systems(
+                    Update,
+                    (
+                        move_cursor,
+                        select_brush,
+                        paint.after(move_cursor).after(select_brush),
+                        edit_enemy.after(move_cursor),
+                        show_cursor.after(paint),
+                        move_camera.after(move_cursor),
+                        control_keys,
+                    )
+                        .run_if(in_state(GameState::Editor)),
+                )
+                .add_systems(
+                    Update,
+                    return_to_editor
+                        .run_if(in_state(GameState::Game))
+                        .run_if(resource_exists::<EditorSession>()),
+                )
+                .add_systems(OnExit(GameState::Editor), despawn_screen::<OnEditorScreen>);
+        }
+    }
+
+    // エディタで編集中（テストプレイ中も含む）。テストプレイから戻っても続きから編集できる
+    #[derive(Resource, Default)]
+    pub struct EditorSession {
+        // Noneならプレイヤーの出現位置から始める
+        cursor: Option<TilePosition>,
+        brush: usize,
+    }
+
+    #[derive(Component)]
+    struct OnEditorScreen;
+
+    // チャンクのタイル（塗ったらチャンクごと作り直す）
+    #[derive(Component)]
+    struct EditorChunk(UVec2);
+
+    #[derive(Component)]
+    struct EditorEnemy;
+
+    #[derive(Component)]
+    struct EditorPlayerStart;
+
+    #[derive(Component)]
+    struct EditorCursor;
+
+    // カーソル位置に表示する塗るものの画像
+    #[derive(Component)]
+    struct EditorBrush;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Brush {
+        Tile(char),
+        Enemy(EnemyKind),
+        PlayerStart,
+    }
+
+    // 凡例のタイル（文字順）、敵、プレイヤーの出現位置の順
+    fn brushes(stage: &StageData) -> Vec<Brush> {
+        let mut tiles = stage.legend.keys().copied().collect::<Vec<char>>();
+        tiles.sort();
+        tiles
+            .into_iter()
+            .map(Brush::Tile)
+            .chain(EnemyKind::ALL.map(Brush::Enemy))
+            .chain([Brush::PlayerStart])
+            .collect()
+    }
+
+    fn tile_translation(stage: &StageData, position: TilePosition, z: f32) -> Vec3 {
+        Vec3::new(
+            TILE_SIZE * position.x as f32,
+            TILE_SIZE * stage.row_from_bottom(position.y) as f32,
+            z,
+        )
+    }
+
+    // プレイヤーの画像の右向きのコマ
+    fn player_sprite() -> Sprite {
+        Sprite {
+            rect: Some(Rect::new(
+                CHARACTER_SIZE * 2.,
+                0.,
+                CHARACTER_SIZE * 3.,
+                CHARACTER_SIZE,
+            )),
+            ..default()
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn editor_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        mut session: ResMut<EditorSession>,
+        mut boss_state: ResMut<NextState<BossState>>,
+    ) {
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Editor");
+
+        // テストプレイでボス戦になっていても戻しておく
+        boss_state.set(BossState::InActive);
+        if session.cursor.is_none() {
+            session.cursor = Some(stage.player_start);
+        }
+
+        for batch in tile_batches(stage, None) {
+            spawn_tile_batch(
+                &mut commands,
+                &asset_server,
+                &mut meshes,
+                &mut materials,
+                &batch,
+                (OnEditorScreen, EditorChunk(batch.chunk)),
+            );
+        }
+        spawn_enemies(&mut commands, &asset_server, &mut texture_atlases, stage);
+
+        commands.spawn((
+            OnEditorScreen,
+            SpriteBundle {
+                texture: asset_server.load("images/character/char.png"),
+                sprite: player_sprite(),
+                transform: Transform::from_translation(tile_translation(
+                    stage,
+                    stage.player_start,
+                    2.,
+                )),
+                ..default()
+            },
+            EditorPlayerStart,
+        ));
+        commands.spawn((
+            OnEditorScreen,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: CURSOR_COLOR,
+                    custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                    ..default()
+                },
+                ..default()
+            },
+            EditorCursor,
+        ));
+        commands.spawn((OnEditorScreen, SpriteBundle::default(), EditorBrush));
+    }
+
+    fn spawn_enemies(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlas>,
+        stage: &StageData,
+    ) {
+        for (index, position) in stage.enemies.iter().enumerate() {
+            spawn_enemy_character(
+                commands,
+                asset_server,
+                texture_atlases,
+                stage,
+                position,
+                Some(index),
+                (OnEditorScreen, EditorEnemy),
+            );
+        }
+    }
+
+    fn move_cursor(
+        keyboard_input: Res<Input<KeyCode>>,
+        mut cursor_moved: EventReader<CursorMoved>,
+        window_query: Query<&Window, With<PrimaryWindow>>,
+        camera_query: Query<(&Camera, &GlobalTransform)>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        mut session: ResMut<EditorSession>,
+    ) {
+        let Some(stage) = stages.get(&current_stage.handle) else {
+            return;
+        };
+        let Some(cursor) = session.cursor else {
+            return;
+        };
+        let mut x = cursor.x as i32;
+        let mut y = cursor.y as i32;
+
+        if keyboard_input.just_pressed(KeyCode::Left) {
+            x -= 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::Right) {
+            x += 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::Up) {
+            y -= 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::Down) {
+            y += 1;
+        }
+
+        // マウスが動いたときだけマウスの下のタイルに合わせる
+        if cursor_moved.iter().last().is_some() {
+            let (camera, camera_transform) = camera_query.single();
+            if let Some(position) = window_query
+                .get_single()
+                .ok()
+                .and_then(|window| window.cursor_position())
+                .and_then(|position| camera.viewport_to_world_2d(camera_transform, position))
+            {
+                x = (position.x / TILE_SIZE).round() as i32;
+                y = stage.height() as i32 - 1 - (position.y / TILE_SIZE).round() as i32;
+            }
+        }
+
+        session.cursor = Some(TilePosition {
+            x: x.clamp(0, stage.width() as i32 - 1) as u32,
+            y: y.clamp(0, stage.height() as i32 - 1) as u32,
+        });
+    }
+
+    fn select_brush(
+        keyboard_input: Res<Input<KeyCode>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        mut session: ResMut<EditorSession>,
+    ) {
+        let Some(stage) = stages.get(&current_stage.handle) else {
+            return;
+        };
+        let count = brushes(stage).len();
+        if keyboard_input.just_pressed(KeyCode::Tab) {
+            let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+            session.brush = if shift {
+                (session.brush + count - 1) % count
+            } else {
+                (session.brush + 1) % count
+            };
+        }
+        let digits = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        for (index, key) in digits.into_iter().enumerate() {
+            if index < count && keyboard_input.just_pressed(key) {
+                session.brush = index;
+            }
+        }
+        session.brush = session.brush.min(count - 1);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn paint(
+        mut commands: Commands,
+        keyboard_input: Res<Input<KeyCode>>,
+        mouse_input: Res<Input<MouseButton>>,
+        asset_server: Res<AssetServer>,
+        mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        mut stages: ResMut<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        session: Res<EditorSession>,
+        chunk_query: Query<(Entity, &EditorChunk)>,
+        enemy_query: Query<Entity, With<EditorEnemy>>,
+        mut player_query: Query<&mut Transform, With<EditorPlayerStart>>,
+    ) {
+        // タイルは押している間塗り続ける
+        let pressed =
+            keyboard_input.pressed(KeyCode::Space) || mouse_input.pressed(MouseButton::Left);
+        let just_pressed = keyboard_input.just_pressed(KeyCode::Space)
+            || mouse_input.just_pressed(MouseButton::Left);
+        if !pressed {
+            return;
+        }
+        let Some(stage) = stages.get_mut(&current_stage.handle) else {
+            return;
+        };
+        let Some(cursor) = session.cursor else {
+            return;
+        };
+
+        match brushes(stage)[session.brush] {
+            Brush::Tile(tile) => {
+                let current = &mut stage.map[cursor.y as usize][cursor.x as usize];
+                if *current == tile {
+                    return;
+                }
+                *current = tile;
+                // 隣のタイルのオートタイルの絵も変わるので、隣のチャンクも作り直す
+                let column = cursor.x as usize;
+                let row = stage.row_from_bottom(cursor.y);
+                let mut chunks = Vec::new();
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (-1, 0), (0, -1)] {
+                    let (Some(column), Some(row)) =
+                        (column.checked_add_signed(dx), row.checked_add_signed(dy))
+                    else {
+                        continue;
+                    };
+                    let chunk = chunk_of(column, row);
+                    if !chunks.contains(&chunk) {
+                        chunks.push(chunk);
+                    }
+                }
+                for (entity, editor_chunk) in chunk_query.iter() {
+                    if chunks.contains(&editor_chunk.0) {
+                        commands.entity(entity).despawn();
+                    }
+                }
+                for chunk in chunks {
+                    for batch in tile_batches(stage, Some(chunk)) {
+                        spawn_tile_batch(
+                            &mut commands,
+                            &asset_server,
+                            &mut meshes,
+                            &mut materials,
+                            &batch,
+                            (OnEditorScreen, EditorChunk(chunk)),
+                        );
+                    }
+                }
+            }
+            Brush::Enemy(kind) if just_pressed => {
+                if stage
+                    .enemies
+                    .iter()
+                    .any(|enemy| enemy.x == cursor.x && enemy.y == cursor.y)
+                {
+                    return;
+                }
+                stage.enemies.push(EnemyPosition {
+                    x: cursor.x,
+                    y: cursor.y,
+                    kind,
+                    facing: Facing::default(),
+                    walk_step: None,
+                    move_lifetime: None,
+                    patrol: None,
+                });
+                for entity in enemy_query.iter() {
+                    commands.entity(entity).despawn();
+                }
+                spawn_enemies(&mut commands, &asset_server, &mut texture_atlases, stage);
+            }
+            Brush::PlayerStart => {
+                stage.player_start = cursor;
+                let mut transform = player_query.single_mut();
+                transform.translation = tile_translation(stage, cursor, transform.translation.z);
+            }
+            _ => {}
+        }
+    }
+
+    // カーソル位置の敵を消す、向きを変える
+    #[allow(clippy::too_many_arguments)]
+    fn edit_enemy(
+        mut commands: Commands,
+        keyboard_input: Res<Input<KeyCode>>,
+        mouse_input: Res<Input<MouseButton>>,
+        asset_server: Res<AssetServer>,
+        mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        mut stages: ResMut<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        session: Res<EditorSession>,
+        enemy_query: Query<Entity, With<EditorEnemy>>,
+    ) {
+        let remove = keyboard_input.any_just_pressed([KeyCode::Delete, KeyCode::Back])
+            || mouse_input.just_pressed(MouseButton::Right);
+        let turn = keyboard_input.just_pressed(KeyCode::F);
+        if !remove && !turn {
+            return;
+        }
+        let Some(stage) = stages.get_mut(&current_stage.handle) else {
+            return;
+        };
+        let Some(cursor) = session.cursor else {
+            return;
+        };
+
+        let at_cursor = |enemy: &EnemyPosition| enemy.x == cursor.x && enemy.y == cursor.y;
+        if !stage.enemies.iter().any(at_cursor) {
+            return;
+        }
+        if remove {
+            stage.enemies.retain(|enemy| !at_cursor(enemy));
+        } else {
+            for enemy in stage.enemies.iter_mut().filter(|enemy| at_cursor(enemy)) {
+                enemy.facing = match enemy.facing {
+                    Facing::Left => Facing::Right,
+                    Facing::Right => Facing::Left,
+                };
+            }
+        }
+        for entity in enemy_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        spawn_enemies(&mut commands, &asset_server, &mut texture_atlases, stage);
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn show_cursor(
+        asset_server: Res<AssetServer>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        session: Res<EditorSession>,
+        mut cursor_query: Query<&mut Transform, (With<EditorCursor>, Without<EditorBrush>)>,
+        mut brush_query: Query<
+            (&mut Transform, &mut Sprite, &mut Handle<Image>),
+            (With<EditorBrush>, Without<EditorCursor>),
+        >,
+    ) {
+        let Some(stage) = stages.get(&current_stage.handle) else {
+            return;
+        };
+        let Some(cursor) = session.cursor else {
+            return;
+        };
+        cursor_query.single_mut().translation = tile_translation(stage, cursor, 3.);
+
+        let (mut transform, mut sprite, mut texture) = brush_query.single_mut();
+        transform.translation = tile_translation(stage, cursor, 4.);
+        let (image, new_sprite) = match brushes(stage)[session.brush] {
+            // オートタイルやアニメーションの画像は左上の絵
+            Brush::Tile(tile) => {
+                let tile = &stage.legend[&tile];
+                let sprite = if tile.grid() == UVec2::ONE {
+                    Sprite::default()
+                } else {
+                    Sprite {
+                        rect: Some(Rect::new(0., 0., TILE_SIZE, TILE_SIZE)),
+                        ..default()
+                    }
+                };
+                (tile.image.clone(), sprite)
+            }
+            // 敵の画像は1コマ目
+            Brush::Enemy(kind) => (
+                kind.image().to_string(),
+                Sprite {
+                    rect: Some(Rect::new(0., 0., CHARACTER_SIZE, CHARACTER_SIZE)),
+                    ..default()
+                },
+            ),
+            Brush::PlayerStart => ("images/character/char.png".to_string(), player_sprite()),
+        };
+        *sprite = Sprite {
+            color: BRUSH_COLOR,
+            ..new_sprite
+        };
+        *texture = asset_server.load(image);
+    }
+
+    fn move_camera(
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        session: Res<EditorSession>,
+        mut camera_query: Query<&mut Transform, With<Camera2d>>,
+    ) {
+        let Some(stage) = stages.get(&current_stage.handle) else {
+            return;
+        };
+        let Some(cursor) = session.cursor else {
+            return;
+        };
+        let bounds = camera_bounds(stage, stage.camera_area());
+        let position = tile_translation(stage, cursor, 0.);
+        let mut transform = camera_query.single_mut();
+        transform.translation.x = position.x.clamp(bounds.min.x, bounds.max.x);
+        transform.translation.y = position.y.clamp(bounds.min.y, bounds.max.y);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn control_keys(
+        mut commands: Commands,
+        keyboard_input: Res<Input<KeyCode>>,
+        mut game_state: ResMut<NextState<GameState>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
+        mut progress: ResMut<CampaignProgress>,
+        mut session: ResMut<EditorSession>,
+    ) {
+        let control = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        if control && keyboard_input.just_pressed(KeyCode::S) {
+            if let Some(stage) = stages.get(&current_stage.handle) {
+                save_stage(stage, &current_stage.entry.map);
+            }
+            return;
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            // テストプレイは出現位置から始める
+            progress.checkpoint = None;
+            progress.boss_checkpoint = false;
+            progress.defeated_enemies.clear();
+            game_state.set(GameState::Game);
+        } else if keyboard_input.any_just_pressed([KeyCode::PageUp, KeyCode::PageDown]) {
+            let campaign = campaigns
+                .get(&current_campaign.handle)
+                .expect("campaign is loaded before GameState::Editor");
+            let count = campaign.stages.len();
+            progress.stage = if keyboard_input.just_pressed(KeyCode::PageUp) {
+                (progress.stage + count - 1) % count
+            } else {
+                (progress.stage + 1) % count
+            };
+            session.cursor = None;
+            game_state.set(GameState::Loading);
+        } else if keyboard_input.just_pressed(KeyCode::Escape) {
+            commands.remove_resource::<EditorSession>();
+            *progress = CampaignProgress::default();
+            game_state.set(GameState::Title);
+        }
+    }
+
+    // キャンペーンが読むステージ定義ファイルに上書きする
+    // Tiledのマップは書き戻せない（別のファイルに保存しても読まれない）ので保存しない
+    fn save_stage(stage: &StageData, map: &str) {
+        if !map.ends_with(".stage.ron") {
+            warn!(
+                "{}: stages made with Tiled cannot be saved from the editor, edit the map in Tiled",
+                map
+            );
+            return;
+        }
+        let path = Path::new(ASSETS_DIR).join(map);
+
+        // 書き出すとコメントが消えるので、コメントのあるファイルは元の内容を残しておく
+        if let Ok(previous) = std::fs::read_to_string(&path) {
+            if previous.contains("//") || previous.contains("/*") {
+                let backup = path.with_extension("ron.bak");
+                match std::fs::write(&backup, previous) {
+                    Ok(()) => warn!(
+                        "{}: comments are not kept, the previous file is saved as {}",
+                        path.display(),
+                        backup.display()
+                    ),
+                    Err(error) => {
+                        error!("{}: {}", backup.display(), error);
+                        return;
+                    }
+                }
+            }
+        }
+
+        // 保存はするが問題があれば知らせる
+        for issue in validate_stage_data(stage) {
+            warn!("{}: {}", path.display(), issue);
+        }
+        let result = stage
+            .to_ron()
+            .map_err(|error| error.to_string())
+            .and_then(|ron| std::fs::write(&path, ron).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => info!("saved {}", path.display()),
+            Err(error) => error!("{}: {}", path.display(), error),
+        }
+    }
+
+    // テストプレイ中にEscでエディタに戻る
+    fn return_to_editor(
+        keyboard_input: Res<Input<KeyCode>>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            game_state.set(GameState::Editor);
+        }
+    }
+}
@@ -1,10 +1,11 @@
+use try_rust_bevy::campaign::*;
 use try_rust_bevy::consts::*;
 use try_rust_bevy::utils::*;
 
 pub mod ending_scene {
     use bevy::prelude::*;
 
-    use super::{despawn_screen, BossState, GameState, StageState};
+    use super::{despawn_screen, BossState, CampaignProgress, GameState};
 
     pub struct EndingPlugin;
 
@@ -65,7 +66,7 @@ pub mod ending_scene {
         time: Res<Time>,
         mut timer: ResMut<SleepTimer>,
         mut game_state: ResMut<NextState<GameState>>,
-        mut stage_state: ResMut<NextState<StageState>>,
+        mut progress: ResMut<CampaignProgress>,
         mut boss_state: ResMut<NextState<BossState>>,
     ) {
         // タイマーを進める
@@ -83,7 +84,7 @@ pub mod ending_scene {
             if scene_number.number == 14 {
                 // State全部リセットする
                 game_state.set(GameState::Title);
-                stage_state.set(StageState::default());
+                *progress = CampaignProgress::default();
                 boss_state.set(BossState::default());
                 scene_number.number = 5;
             } else {
@@ -2,19 +2,23 @@ pub mod game_scene {
     use std::time::Duration;
 
     use bevy::prelude::*;
-    use bevy::sprite::collide_aabb::{collide, Collision};
+    use bevy::sprite::collide_aabb::collide;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
     use rand::Rng;
+    use try_rust_bevy::campaign::*;
+    use try_rust_bevy::collision::{CollisionMap, Support};
     use try_rust_bevy::consts::*;
+    use try_rust_bevy::movement::{stick_input, walk_speed, Abilities, JumpMotion};
+    use try_rust_bevy::stage::*;
+    use try_rust_bevy::tilemap::*;
     use try_rust_bevy::utils::*;
 
+    use crate::editor::editor_scene::EditorSession;
+
     const FPS: usize = 60;
     const TIME_1F: f32 = 1. / FPS as f32;
-    const CHARACTER_SIZE: f32 = 32.;
     const BOSS_SIZE: f32 = 64.;
-    const TILE_SIZE: f32 = 32.;
     const LIFE_SIZE: f32 = 16.;
-    const PLAYER_JUMP_FORCE: f32 = 44.;
-    const PLAYER_WALK_STEP: f32 = 4.;
     const PLAYER_WEAPON_STEP: f32 = 8.;
     const PLAYER_WEAPON_THUNDER_STEP: f32 = 12.;
     const PLAYER_WEAPON_LIFETIME_FOR_SWORD: f32 = 17. * TIME_1F;
@@ -23,30 +27,107 @@ pub mod game_scene {
     const ENEMY_WEAPON_LIFETIME: f32 = 60. * TIME_1F;
     const ENEMY_WALK_STEP: f32 = 1.;
     const ENEMY_RIZZARD_WALK_STEP: f32 = 4.;
+    // 2つ目以降の行動を続けるフレーム数（最初の行動はEnemyKind::move_lifetime）
+    const ENEMY_MOVE_LIFETIME: usize = 30;
     const BOSS_WEAPON_STEP: f32 = 4.;
     const BOSS_WEAPON_LIFETIME: f32 = 90. * TIME_1F;
     const BOSS_DAMAGE_COOLTIME: f32 = 30. * TIME_1F;
+    const PLAYER_DAMAGE_COOLTIME: f32 = 90. * TIME_1F;
+    const PLAYER_KNOCKBACK_SPEED: f32 = 6.;
+    const PLAYER_KNOCKBACK_FORCE: f32 = 20.;
     const BOSS_WALK_STEP: f32 = 2.;
     const BOSS_MOVE_LIFETIME: usize = 40;
-    const GRAVITY: f32 = 9.81;
-    const GRAVITY_TIME_STEP: f32 = 0.24; // FPS通りだと重力加速が少ないので経過時間を補正
-    const MAP_WIDTH_TILES: u32 = 100;
-    const MAP_HEIGHT_TILES: u32 = 15;
+    const MAX_LIVES: u32 = 9;
+    const CHECKPOINT_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+    const CHECKPOINT_REACHED_COLOR: Color = Color::rgb(1., 0.8, 0.2);
 
     #[derive(Component)]
     struct OnGameScreen;
 
+    // 読み込んだステージの大きさ（タイル数）
+    #[derive(Resource)]
+    struct StageSize {
+        width: u32,
+        height: u32,
+    }
+
+    // 1フレームの移動量（ピクセル）
     #[derive(Component, Deref, DerefMut)]
     struct Velocity(Vec2);
 
     #[derive(Component)]
     struct Character;
 
+    // 飾りのレイヤー。カメラがanchorから動いた分のscroll倍だけ動いて見える
+    #[derive(Component)]
+    struct ParallaxLayer {
+        scroll: Vec2,
+        // レイヤーを定義どおりの位置に描くときのカメラの位置
+        anchor: Vec2,
+    }
+
+    // 動く足場、崩れる足場（上からだけ乗れる）
+    #[derive(Component)]
+    struct DynamicPlatform {
+        support: Support,
+        active: bool,
+    }
+
+    // プレイヤーが出入りしたときにactionsを実行する範囲
+    #[derive(Component)]
+    struct Trigger {
+        actions: Vec<TriggerAction>,
+        size: Vec2,
+        once: bool,
+        // 範囲内にいる（Enterを送ってまだExitを送っていない）
+        inside: bool,
+        fired: bool,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum TriggerEventKind {
+        Enter,
+        Exit,
+    }
+
+    #[derive(Event)]
+    struct TriggerEvent {
+        trigger: Entity,
+        kind: TriggerEventKind,
+    }
+
+    // トリガーで表示しているメッセージ画像
     #[derive(Component)]
-    struct Collider;
+    struct TriggerMessage {
+        trigger: Entity,
+    }
+
+    // トリガーでカメラを固定している位置（固定したトリガーから出たら解除）
+    #[derive(Resource, Default)]
+    struct CameraLock(Option<(Entity, Vec2)>);
+
+    // 触れるとリスポーン位置になる
+    #[derive(Component)]
+    struct Checkpoint {
+        // StageData::checkpointsの番号
+        index: usize,
+    }
+
+    #[derive(Component)]
+    struct MovingPlatform {
+        // 足場の中心が通る位置
+        waypoints: Vec<Vec2>,
+        next: usize,
+        speed: f32,
+    }
 
     #[derive(Component)]
-    struct Wall;
+    struct CrumblingPlatform {
+        crumble_timer: Timer,
+        respawn_timer: Timer,
+        // プレイヤーが乗ったら崩れ始める
+        stepped: bool,
+    }
 
     #[derive(Component)]
     struct AnimationIndices {
@@ -57,6 +138,13 @@ pub mod game_scene {
     #[derive(Component, Deref, DerefMut)]
     struct AnimationTimer(Timer);
 
+    // アニメーションするタイルのメッシュ（コマが変わったら作り直す）
+    #[derive(Component)]
+    struct AnimatedTiles {
+        batch: TileBatch,
+        frame: usize,
+    }
+
     #[derive(Event, Default)]
     struct CollisionEvent;
 
@@ -66,9 +154,51 @@ pub mod game_scene {
         life: i32,
     }
 
+    // プレイヤーの体力の表示（体力が2以上のときだけ）
+    #[derive(Component)]
+    struct PlayerLife {
+        index: u32,
+        // カメラの中心からの位置
+        offset: Vec2,
+    }
+
+    // 残機の表示（残機の数だけプレイヤーを小さく並べる）
+    #[derive(Component)]
+    struct LivesIcon {
+        index: u32,
+        // カメラの中心からの位置
+        offset: Vec2,
+    }
+
+    // 取ると残機が1増えるアイテム
+    #[derive(Component)]
+    struct ExtraLifeItem {
+        // StageData::extra_livesの番号
+        index: usize,
+    }
+
+    // 取ると能力が使えるようになるアイテム
+    #[derive(Component)]
+    struct AbilityItem {
+        ability: Ability,
+    }
+
     #[derive(Component)]
     struct BossLife {
         index: u8,
+        // カメラの中心からの位置
+        offset: Vec2,
+    }
+
+    // カメラの中心が動ける範囲（ボス戦中はボス戦の舞台に限る）
+    #[derive(Resource, Deref)]
+    struct CameraBounds(Rect);
+
+    // ボスの隕石などが降ってくる列と高さ
+    #[derive(Resource)]
+    struct BossHazardArea {
+        columns: std::ops::RangeInclusive<u32>,
+        top: f32,
     }
 
     #[derive(Clone, PartialEq)]
@@ -86,26 +216,25 @@ pub mod game_scene {
         index: usize,
     }
 
-    #[derive(PartialEq)]
-    enum EnemyKind {
-        Slime,
-        Lizard,
-        Wizard,
-        RedDemon,
-    }
-
     #[derive(Component)]
     struct Enemy {
         kind: EnemyKind,
+        // StageData::enemiesの番号（トリガーで出現した敵はNone）
+        index: Option<usize>,
     }
 
     #[derive(Component)]
     struct EnemyCharacter {
         direction: AllDirection,
+        // 現在の行動の残りフレーム数
         move_lifetime: usize,
+        // 1つの行動を続けるフレーム数
+        move_duration: usize,
         stop: bool,
         weapon_cooldown: Timer,
         walk_step: f32,
+        // 動ける範囲（なければステージ全体）
+        patrol: Option<Rect>,
     }
 
     #[derive(Clone, PartialEq)]
@@ -123,10 +252,20 @@ pub mod game_scene {
     #[derive(Component)]
     struct Player {
         direction: Direction,
-        walk: bool,
+        // 横の入力の強さ（-1から1）
+        walk: f32,
         grounded: bool,
+        // 乗っている動く足場、崩れる足場
+        support: Option<Entity>,
         live: bool,
+        // 体力と、ダメージを受けた後の無敵時間
+        life: u32,
+        damage_cooldown: Timer,
         jump_status: PlayerJumpStatus,
+        climb_status: PlayerClimbStatus,
+        dash_status: PlayerDashStatus,
+        // 使える能力（CampaignProgressから引き継ぐ）
+        abilities: Abilities,
         weapon_limit: PlayerWeaponLimit,
     }
 
@@ -155,18 +294,48 @@ pub mod game_scene {
     }
 
     #[derive(Component)]
-    struct PlayerWeaponLimitStatus;
+    struct PlayerWeaponLimitStatus {
+        // カメラの中心からの高さ
+        offset_y: f32,
+    }
 
     #[derive(Component)]
     struct PlayerWeaponLimitStatusNumber {
         kind: PlayerWeaponKind,
         current: u8,
+        // カメラの中心からの高さ
+        offset_y: f32,
+    }
+
+    // はしごの登り降り
+    struct PlayerClimbStatus {
+        climb: bool,
+        up: bool,
+        down: bool,
     }
 
     struct PlayerJumpStatus {
-        jump: bool,
-        fall_time: f32,
-        jump_start_y: f32,
+        motion: JumpMotion,
+        // このフレームでジャンプボタンを押している
+        pressed: bool,
+        // 押したジャンプを受け付ける残りフレーム数
+        buffer: usize,
+        // 足場から歩いて離れてもジャンプできる残りフレーム数
+        coyote: usize,
+        // 空中で跳べる残り回数（二段ジャンプ）
+        air_jumps: u32,
+    }
+
+    struct PlayerDashStatus {
+        // このフレームでダッシュボタンを押した
+        pressed: bool,
+        // ダッシュの残りフレーム数
+        frames: usize,
+        direction: f32,
+        // 次にダッシュできるまでのフレーム数
+        cooldown: usize,
+        // 空中でまだダッシュできる（着地するまで1回）
+        air: bool,
     }
 
     #[derive(Component)]
@@ -193,30 +362,40 @@ pub mod game_scene {
             app.insert_resource(FixedTime::new_from_secs(TIME_1F)) // 60FPS
                 .add_state::<BossState>()
                 .add_event::<CollisionEvent>()
+                .add_event::<TriggerEvent>()
                 .add_systems(OnEnter(GameState::Game), (game_setup, spawn_enemy))
                 .add_systems(OnEnter(BossState::Active), boss_setup)
+                // タイルのアニメーションはエディタでも動かす
+                .add_systems(
+                    Update,
+                    animate_tiles
+                        .run_if(in_state(GameState::Game).or_else(in_state(GameState::Editor))),
+                )
                 .add_systems(
                     Update,
                     (
                         animate_sprite,
                         move_camera,
                         move_player_weapon_limit.after(move_camera),
+                        move_trigger_message.after(move_camera),
+                        move_boss_life.after(move_camera),
+                        move_player_life.after(move_camera),
+                        move_lives_icon.after(move_camera),
+                        move_parallax_layer.after(move_camera),
                         die_counter,
                     )
                         .run_if(in_state(GameState::Game)),
                 )
                 .add_systems(
-                    Update,
-                    (check_stage1_clear_system)
-                        .run_if(in_state(GameState::Game))
-                        .run_if(in_state(StageState::Stage1)),
-                )
-                .add_systems(
-                    Update,
-                    (check_stage2_appear_boss_system)
-                        .run_if(in_state(GameState::Game))
-                        .run_if(in_state(StageState::Stage2).or_else(in_state(StageState::Boss)))
-                        .run_if(in_state(BossState::InActive)),
+                    FixedUpdate,
+                    (
+                        check_trigger_system.after(check_collision_wall_system),
+                        trigger_stage_system.after(check_trigger_system),
+                        trigger_camera_system.after(check_trigger_system),
+                        trigger_message_system.after(check_trigger_system),
+                        trigger_spawn_enemy_system.after(check_trigger_system),
+                    )
+                        .run_if(in_state(GameState::Game)),
                 )
                 .add_systems(
                     FixedUpdate,
@@ -230,7 +409,6 @@ pub mod game_scene {
                         boss_flash_system,
                     )
                         .run_if(in_state(GameState::Game))
-                        .run_if(in_state(StageState::Boss))
                         .run_if(in_state(BossState::Active)),
                 )
                 .add_systems(
@@ -238,20 +416,29 @@ pub mod game_scene {
                     (
                         control_player_system,
                         control_player_system_for_gamepad,
+                        move_platform_system,
                         check_collision_wall_system
                             .after(control_player_system)
-                            .after(control_player_system_for_gamepad),
+                            .after(control_player_system_for_gamepad)
+                            .after(move_platform_system),
+                        check_collision_hazard_system.after(check_collision_wall_system),
+                        check_checkpoint_system.after(check_collision_wall_system),
+                        crumble_platform_system.after(check_collision_wall_system),
                         check_collision_enemy_system,
                         check_collision_player_weapon_system,
                         check_collision_enemy_weapon_system,
                         check_collision_player_weapon_limit_item_system,
+                        check_collision_extra_life_item_system,
+                        check_collision_ability_item_system,
                         check_player_weapon_limit_status_system,
                         control_enemy_system,
                         move_enemy_system
+                            .after(move_platform_system)
                             .after(control_enemy_system)
                             .after(control_boss_system),
                         move_enemy_weapon_system,
                         move_player_weapon_system,
+                        player_flash_system,
                     )
                         .run_if(in_state(GameState::Game)),
                 )
@@ -259,12 +446,44 @@ pub mod game_scene {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn game_setup(
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-        stage_state: Res<State<StageState>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        progress: Res<CampaignProgress>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
     ) {
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Game");
+        let player_health = campaigns
+            .get(&current_campaign.handle)
+            .expect("campaign is loaded before GameState::Game")
+            .player_health;
+
+        commands.insert_resource(StageSize {
+            width: stage.width() as u32,
+            height: stage.height() as u32,
+        });
+        commands.insert_resource(CollisionMap::from_stage(stage, TILE_SIZE));
+
+        // ステージBGM
+        if let Some(music) = &current_stage.entry.music {
+            commands.spawn((
+                OnGameScreen,
+                AudioBundle {
+                    source: asset_server.load(music),
+                    settings: PlaybackSettings::LOOP,
+                },
+            ));
+        }
+
         // デスタイマー
         commands.insert_resource(DeathTimer(Timer::from_seconds(2.0, TimerMode::Once)));
         // サンダーを最初だけ一瞬止めるためのタイマー
@@ -274,6 +493,14 @@ pub mod game_scene {
         )));
 
         // Player
+        // チェックポイントに触れていたらそこからリスポーン
+        let respawn = match &stage.boss_arena {
+            // ボス戦のリスポーン位置はステージ途中
+            Some(arena) if progress.boss_checkpoint => arena.respawn,
+            _ => progress
+                .checkpoint
+                .map_or(stage.player_start, |index| stage.checkpoints[index]),
+        };
         let texture_handle = asset_server.load("images/character/char.png");
         let texture_atlas = TextureAtlas::from_grid(
             texture_handle,
@@ -291,12 +518,8 @@ pub mod game_scene {
                 texture_atlas: texture_atlas_handle,
                 sprite: TextureAtlasSprite::new(animation_indices.first),
                 transform: Transform::from_xyz(
-                    match stage_state.get() {
-                        // ボス戦のリスポーン位置はステージ途中
-                        StageState::Boss => TILE_SIZE * 75.,
-                        _ => TILE_SIZE * 2.,
-                    },
-                    TILE_SIZE * 2.,
+                    TILE_SIZE * respawn.x as f32,
+                    TILE_SIZE * stage.row_from_bottom(respawn.y) as f32,
                     2.,
                 ),
                 ..default()
@@ -305,14 +528,34 @@ pub mod game_scene {
             AnimationTimer(Timer::from_seconds(0.33, TimerMode::Repeating)),
             Player {
                 direction: Direction::Right,
-                walk: false,
+                walk: 0.,
                 grounded: true,
+                support: None,
                 live: true,
+                life: player_health,
+                damage_cooldown: Timer::from_seconds(PLAYER_DAMAGE_COOLTIME, TimerMode::Once)
+                    .tick(Duration::from_secs_f32(PLAYER_DAMAGE_COOLTIME))
+                    .clone(),
                 jump_status: PlayerJumpStatus {
-                    jump: false,
-                    fall_time: 0.,
-                    jump_start_y: 0.,
+                    motion: JumpMotion::falling(0.),
+                    pressed: false,
+                    buffer: 0,
+                    coyote: 0,
+                    air_jumps: PLAYER_AIR_JUMPS,
                 },
+                climb_status: PlayerClimbStatus {
+                    climb: false,
+                    up: false,
+                    down: false,
+                },
+                dash_status: PlayerDashStatus {
+                    pressed: false,
+                    frames: 0,
+                    direction: 1.,
+                    cooldown: 0,
+                    air: true,
+                },
+                abilities: progress.abilities,
                 weapon_limit: PlayerWeaponLimit {
                     fire: 3,
                     ice: 3,
@@ -323,72 +566,166 @@ pub mod game_scene {
             Velocity(Vec2::new(0.0, 0.0)),
         ));
 
-        let mut map = match stage_state.get() {
-            StageState::Stage1 => STAGE1_MAP,
-            StageState::Stage2 | StageState::Boss => STAGE2_MAP,
-        };
-        map.reverse();
-
-        // マップ描画
-        for (row, map_str) in map.iter().enumerate() {
-            let map_chars = map_str.chars().collect::<Vec<char>>();
-            for (column, map_char) in map_chars.iter().enumerate() {
-                if *map_char == 'A' || *map_char == 'B' {
-                    // Background
-                    commands.spawn((
+        // マップ描画（チャンクと画像ごとに1つのメッシュ）
+        for batch in tile_batches(stage, None) {
+            spawn_tile_batch(
+                &mut commands,
+                &asset_server,
+                &mut meshes,
+                &mut materials,
+                &batch,
+                OnGameScreen,
+            );
+        }
+
+        // 飾りのレイヤー（カメラが左下にあるときに定義どおりの位置になる）
+        let stage_bounds = camera_bounds(stage, stage.camera_area());
+        for layer in stage.layers.iter() {
+            let scroll = Vec2::new(layer.scroll, layer.scroll_y.unwrap_or(layer.scroll));
+            for batch in layer_batches(stage, layer) {
+                spawn_tile_batch(
+                    &mut commands,
+                    &asset_server,
+                    &mut meshes,
+                    &mut materials,
+                    &batch,
+                    (
                         OnGameScreen,
-                        SpriteBundle {
-                            texture: asset_server.load(if *map_char == 'A' {
-                                match stage_state.get() {
-                                    StageState::Stage1 => "images/map/map_1.png",
-                                    StageState::Stage2 | StageState::Boss => {
-                                        "images/map/map2_1.png"
-                                    }
-                                }
-                            } else {
-                                match stage_state.get() {
-                                    StageState::Stage1 => "images/map/map_2.png",
-                                    StageState::Stage2 | StageState::Boss => {
-                                        "images/map/map2_2.png"
-                                    }
-                                }
-                            }),
-                            transform: Transform {
-                                translation: Vec3::new(
-                                    TILE_SIZE * column as f32,
-                                    CHARACTER_SIZE * row as f32,
-                                    -1.,
-                                ),
-                                ..default()
-                            },
-                            ..default()
+                        ParallaxLayer {
+                            scroll,
+                            anchor: stage_bounds.min,
                         },
-                    ));
-                }
-                if *map_char == 'C' {
-                    // Wall
-                    commands.spawn((
-                        OnGameScreen,
-                        SpriteBundle {
-                            texture: asset_server.load(match stage_state.get() {
-                                StageState::Stage1 => "images/map/map_3.png",
-                                StageState::Stage2 | StageState::Boss => "images/map/map2_3.png",
-                            }),
-                            transform: Transform {
-                                translation: Vec3::new(
-                                    TILE_SIZE * column as f32,
-                                    CHARACTER_SIZE * row as f32,
-                                    0.,
-                                ),
-                                ..default()
-                            },
-                            ..default()
+                    ),
+                );
+            }
+        }
+
+        spawn_platforms(&mut commands, &asset_server, stage);
+        spawn_triggers(&mut commands, stage);
+        commands.insert_resource(CameraLock::default());
+        commands.insert_resource(CameraBounds(stage_bounds));
+
+        // チェックポイント
+        for (index, checkpoint) in stage.checkpoints.iter().enumerate() {
+            commands.spawn((
+                OnGameScreen,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: if progress.checkpoint == Some(index) {
+                            CHECKPOINT_REACHED_COLOR
+                        } else {
+                            CHECKPOINT_COLOR
                         },
-                        Wall,
-                        Collider,
-                    ));
-                }
+                        custom_size: Some(Vec2::new(TILE_SIZE / 4., TILE_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(
+                        TILE_SIZE * checkpoint.x as f32,
+                        TILE_SIZE * stage.row_from_bottom(checkpoint.y) as f32,
+                        1.,
+                    ),
+                    ..default()
+                },
+                Checkpoint { index },
+            ));
+        }
+
+        // プレイヤーの体力（一撃モードなら表示しない。位置はmove_player_lifeでカメラに合わせる）
+        if player_health > 1 {
+            for index in 1..=player_health {
+                commands.spawn((
+                    OnGameScreen,
+                    SpriteBundle {
+                        texture: asset_server.load("images/status/life.png"),
+                        transform: Transform::from_xyz(0., 0., 3.),
+                        ..default()
+                    },
+                    PlayerLife {
+                        index,
+                        // 画面左上の武器の残数表示の右から表示する
+                        offset: Vec2::new(
+                            -320. + TILE_SIZE + LIFE_SIZE / 2. + LIFE_SIZE * (index - 1) as f32,
+                            240. - LIFE_SIZE / 2.,
+                        ),
+                    },
+                ));
+            }
+        }
+
+        // 残機（位置はmove_lives_iconでカメラに合わせる）
+        let texture_atlas = TextureAtlas::from_grid(
+            asset_server.load("images/character/char.png"),
+            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+            5,
+            1,
+            None,
+            None,
+        );
+        let texture_atlas_handle = texture_atlases.add(texture_atlas);
+        for index in 1..=MAX_LIVES {
+            commands.spawn((
+                OnGameScreen,
+                SpriteSheetBundle {
+                    texture_atlas: texture_atlas_handle.clone(),
+                    sprite: TextureAtlasSprite::new(2),
+                    transform: Transform {
+                        translation: Vec3::new(0., 0., 3.),
+                        scale: Vec3::new(0.5, 0.5, 1.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                LivesIcon {
+                    index,
+                    // 画面左下から表示する
+                    offset: Vec2::new(
+                        -320. + LIFE_SIZE / 2. + LIFE_SIZE * (index - 1) as f32,
+                        -240. + LIFE_SIZE / 2.,
+                    ),
+                },
+            ));
+        }
+
+        // 残機アイテム（取ったものは出さない）
+        for (index, extra_life) in stage.extra_lives.iter().enumerate() {
+            if progress.extra_lives.contains(&index) {
+                continue;
             }
+            commands.spawn((
+                OnGameScreen,
+                SpriteBundle {
+                    texture: asset_server.load("images/status/extra_life.png"),
+                    transform: Transform::from_xyz(
+                        TILE_SIZE * extra_life.x as f32,
+                        TILE_SIZE * stage.row_from_bottom(extra_life.y) as f32,
+                        1.,
+                    ),
+                    ..default()
+                },
+                ExtraLifeItem { index },
+            ));
+        }
+
+        // 能力のアイテム（もう使える能力なら出さない）
+        for item in stage.ability_items.iter() {
+            if progress.abilities.has(item.ability) {
+                continue;
+            }
+            commands.spawn((
+                OnGameScreen,
+                SpriteBundle {
+                    texture: asset_server.load(item.ability.image()),
+                    transform: Transform::from_xyz(
+                        TILE_SIZE * item.x as f32,
+                        TILE_SIZE * stage.row_from_bottom(item.y) as f32,
+                        1.,
+                    ),
+                    ..default()
+                },
+                AbilityItem {
+                    ability: item.ability,
+                },
+            ));
         }
 
         // プレイヤーの武器の残数表示
@@ -403,19 +740,20 @@ pub mod game_scene {
         );
         let texture_atlas_handle = texture_atlases.add(texture_atlas);
         for i in 1..=3 {
-            let y = CHARACTER_SIZE * (14 - i + 1) as f32;
+            // 画面の上端から順に並べる（位置はmove_player_weapon_limitでカメラに合わせる）
+            let offset_y = 240. - TILE_SIZE / 2. - CHARACTER_SIZE * (i - 1) as f32;
             // 残数の背景
             commands.spawn((
                 OnGameScreen,
                 SpriteBundle {
                     texture: asset_server.load(format!("images/status/item_{}.png", i)),
                     transform: Transform {
-                        translation: Vec3::new(0., y, 2.),
+                        translation: Vec3::new(0., offset_y, 2.),
                         ..default()
                     },
                     ..default()
                 },
-                PlayerWeaponLimitStatus,
+                PlayerWeaponLimitStatus { offset_y },
             ));
 
             // 残数の数字
@@ -426,7 +764,7 @@ pub mod game_scene {
                     texture_atlas: texture_atlas_handle.clone(),
                     sprite: TextureAtlasSprite::new(animation_indices.last),
                     transform: Transform {
-                        translation: Vec3::new(0., y, 3.),
+                        translation: Vec3::new(0., offset_y, 3.),
                         ..default()
                     },
                     ..default()
@@ -440,47 +778,308 @@ pub mod game_scene {
                         _ => PlayerWeaponKind::Fire,
                     },
                     current: 0,
+                    offset_y,
+                },
+            ));
+        }
+    }
+
+    // 同じ画像のタイルをまとめたメッシュを出す（エディタでも使う）。判定はCollisionMapで行う
+    pub fn spawn_tile_batch(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        batch: &TileBatch,
+        marker: impl Bundle,
+    ) -> Entity {
+        let mut entity = commands.spawn((
+            marker,
+            MaterialMesh2dBundle {
+                mesh: meshes.add(batch.mesh(TILE_SIZE, 0)).into(),
+                material: materials.add(asset_server.load(&batch.tile.image).into()),
+                transform: Transform::from_xyz(0., 0., batch.z),
+                ..default()
+            },
+        ));
+        if let Some(animation) = batch.tile.animation {
+            entity.insert((
+                AnimationIndices {
+                    first: 0,
+                    last: animation.frames.max(1) as usize - 1,
+                },
+                AnimationTimer(Timer::from_seconds(
+                    animation.frame_time,
+                    TimerMode::Repeating,
+                )),
+                AnimatedTiles {
+                    batch: batch.clone(),
+                    frame: 0,
+                },
+            ));
+        }
+        entity.id()
+    }
+
+    // ステージのトリガーとゴールの範囲
+    fn spawn_triggers(commands: &mut Commands, stage: &StageData) {
+        let goal = match stage.goal {
+            // ゴールに触れたらクリア
+            Some(goal) => TriggerDefinition {
+                x: goal.x,
+                y: goal.y,
+                width: 1,
+                height: 1,
+                actions: vec![TriggerAction::ClearStage],
+                once: false,
+            },
+            // ゴールがなければ右端に着いたらクリア
+            None => TriggerDefinition {
+                x: stage.width() as u32 - 1,
+                y: 0,
+                width: 1,
+                height: stage.height() as u32,
+                actions: vec![TriggerAction::ClearStage],
+                once: false,
+            },
+        };
+
+        for trigger in stage.triggers.iter().chain([&goal]) {
+            let width = trigger.width.max(1);
+            let height = trigger.height.max(1);
+            let bottom = stage.row_from_bottom(trigger.y + height - 1);
+            commands.spawn((
+                OnGameScreen,
+                TransformBundle::from_transform(Transform::from_xyz(
+                    TILE_SIZE * (trigger.x as f32 + (width - 1) as f32 / 2.),
+                    TILE_SIZE * (bottom as f32 + (height - 1) as f32 / 2.),
+                    0.,
+                )),
+                Trigger {
+                    actions: trigger.actions.clone(),
+                    size: Vec2::new(TILE_SIZE * width as f32, TILE_SIZE * height as f32),
+                    once: trigger.once,
+                    inside: false,
+                    fired: false,
                 },
             ));
         }
     }
 
+    // 動く足場、崩れる足場
+    fn spawn_platforms(
+        commands: &mut Commands,
+        asset_server: &Res<AssetServer>,
+        stage: &StageData,
+    ) {
+        for platform in stage.moving_platforms.iter() {
+            let start = platform.path[0];
+            let (entity, support) = spawn_platform(
+                commands,
+                asset_server,
+                stage,
+                start,
+                platform.width,
+                &platform.image,
+            );
+            // 経路は左端のタイルで書かれているので足場の中心に直す
+            let offset = support.center.x - TILE_SIZE * start.x as f32;
+            let waypoints = platform
+                .path
+                .iter()
+                .map(|position| {
+                    Vec2::new(
+                        TILE_SIZE * position.x as f32 + offset,
+                        TILE_SIZE * stage.row_from_bottom(position.y) as f32,
+                    )
+                })
+                .collect::<Vec<Vec2>>();
+            commands.entity(entity).insert(MovingPlatform {
+                next: 1 % waypoints.len(),
+                waypoints,
+                speed: platform.speed,
+            });
+        }
+
+        for platform in stage.crumbling_platforms.iter() {
+            let (entity, _) = spawn_platform(
+                commands,
+                asset_server,
+                stage,
+                TilePosition {
+                    x: platform.x,
+                    y: platform.y,
+                },
+                platform.width,
+                &platform.image,
+            );
+            commands.entity(entity).insert(CrumblingPlatform {
+                crumble_timer: Timer::from_seconds(platform.crumble_time, TimerMode::Once),
+                respawn_timer: Timer::from_seconds(platform.respawn_time, TimerMode::Once),
+                stepped: false,
+            });
+        }
+    }
+
+    // 左端のタイルの位置からwidthタイル分の足場を出す
+    fn spawn_platform(
+        commands: &mut Commands,
+        asset_server: &Res<AssetServer>,
+        stage: &StageData,
+        position: TilePosition,
+        width: u32,
+        image: &str,
+    ) -> (Entity, Support) {
+        let width = width.max(1);
+        let left = Vec2::new(
+            TILE_SIZE * position.x as f32,
+            TILE_SIZE * stage.row_from_bottom(position.y) as f32,
+        );
+        let support = Support {
+            center: left + Vec2::new(TILE_SIZE * (width - 1) as f32 / 2., 0.),
+            size: Vec2::new(TILE_SIZE * width as f32, TILE_SIZE),
+            delta: Vec2::ZERO,
+        };
+        let texture: Handle<Image> = asset_server.load(image);
+        let entity = commands
+            .spawn((
+                OnGameScreen,
+                SpriteBundle {
+                    texture: texture.clone(),
+                    transform: Transform::from_translation(left.extend(0.)),
+                    ..default()
+                },
+                DynamicPlatform {
+                    support,
+                    active: true,
+                },
+            ))
+            // 2タイル目以降は子として並べる
+            .with_children(|parent| {
+                for i in 1..width {
+                    parent.spawn(SpriteBundle {
+                        texture: texture.clone(),
+                        transform: Transform::from_xyz(TILE_SIZE * i as f32, 0., 0.),
+                        ..default()
+                    });
+                }
+            })
+            .id();
+        (entity, support)
+    }
+
+    fn move_platform_system(
+        mut query: Query<(&mut DynamicPlatform, &mut MovingPlatform, &mut Transform)>,
+    ) {
+        for (mut platform, mut moving_platform, mut transform) in &mut query {
+            let target = moving_platform.waypoints[moving_platform.next];
+            let offset = target - platform.support.center;
+            let step = if offset.length() <= moving_platform.speed {
+                // 次の位置へ（最後まで行ったら最初に戻る）
+                moving_platform.next = (moving_platform.next + 1) % moving_platform.waypoints.len();
+                offset
+            } else {
+                offset.normalize() * moving_platform.speed
+            };
+            platform.support.center += step;
+            platform.support.delta = step;
+
+            // 表示は左端のタイルが基準
+            transform.translation.x =
+                platform.support.center.x - (platform.support.size.x - TILE_SIZE) / 2.;
+            transform.translation.y = platform.support.center.y;
+        }
+    }
+
+    fn crumble_platform_system(
+        player_query: Query<&Player>,
+        mut query: Query<(
+            Entity,
+            &mut DynamicPlatform,
+            &mut CrumblingPlatform,
+            &mut Visibility,
+        )>,
+        time: Res<Time>,
+    ) {
+        let player = player_query.single();
+        for (entity, mut platform, mut crumbling_platform, mut visibility) in &mut query {
+            if platform.active {
+                if player.support == Some(entity) {
+                    crumbling_platform.stepped = true;
+                }
+                if crumbling_platform.stepped
+                    && crumbling_platform
+                        .crumble_timer
+                        .tick(time.delta())
+                        .finished()
+                {
+                    // 崩れる
+                    platform.active = false;
+                    *visibility = Visibility::Hidden;
+                    crumbling_platform.stepped = false;
+                    crumbling_platform.crumble_timer.reset();
+                }
+            } else if crumbling_platform
+                .respawn_timer
+                .tick(time.delta())
+                .finished()
+            {
+                // 元に戻る
+                platform.active = true;
+                *visibility = Visibility::Inherited;
+                crumbling_platform.respawn_timer.reset();
+            }
+        }
+    }
+
     // ボス戦開始時のセットアップ
     fn boss_setup(
         mut commands: Commands,
         mut enemy_query: Query<Entity, With<Enemy>>,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        mut collision_map: ResMut<CollisionMap>,
     ) {
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Game");
+        let arena = stage
+            .boss_arena
+            .as_ref()
+            .expect("stage with a StartBoss trigger has a boss arena");
+
+        // カメラをボス戦の舞台に限る
+        let camera_area = arena.camera.unwrap_or(stage.camera_area());
+        commands.insert_resource(CameraBounds(camera_bounds(stage, camera_area)));
+        let hazard_area = arena.hazard.unwrap_or(camera_area);
+        commands.insert_resource(BossHazardArea {
+            columns: hazard_area.x..=hazard_area.last().x,
+            top: TILE_SIZE * stage.row_from_bottom(hazard_area.y) as f32,
+        });
+
         // 壁を出現
-        let walls = [
-            (78, 2),
-            (78, 3),
-            (99, 2),
-            (99, 3),
-            (99, 4),
-            (99, 5),
-            (99, 6),
-            (99, 7),
-            (99, 8),
-        ];
-        for (column, row) in walls {
+        for wall in arena.walls.iter() {
+            collision_map.set_tile(
+                wall.x as usize,
+                stage.row_from_bottom(wall.y),
+                TileKind::Wall,
+            );
             commands.spawn((
                 OnGameScreen,
                 SpriteBundle {
-                    texture: asset_server.load("images/map/map2_3.png"),
+                    texture: asset_server.load(&arena.wall_image),
                     transform: Transform {
                         translation: Vec3::new(
-                            TILE_SIZE * column as f32,
-                            CHARACTER_SIZE * row as f32,
+                            TILE_SIZE * wall.x as f32,
+                            CHARACTER_SIZE * stage.row_from_bottom(wall.y) as f32,
                             0.,
                         ),
                         ..default()
                     },
                     ..default()
                 },
-                Wall,
-                Collider,
             ));
         }
 
@@ -506,7 +1105,11 @@ pub mod game_scene {
             SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle,
                 sprite: TextureAtlasSprite::new(animation_indices.first),
-                transform: Transform::from_xyz(TILE_SIZE * 96., TILE_SIZE * 2.5, 0.),
+                transform: Transform::from_xyz(
+                    TILE_SIZE * arena.boss.x as f32,
+                    TILE_SIZE * (stage.row_from_bottom(arena.boss.y) as f32 + 0.5),
+                    0.,
+                ),
                 ..default()
             },
             animation_indices,
@@ -521,31 +1124,29 @@ pub mod game_scene {
                 direction: AllDirection::Left,
                 stop: false,
                 move_lifetime: BOSS_MOVE_LIFETIME,
+                move_duration: BOSS_MOVE_LIFETIME,
                 walk_step: BOSS_WALK_STEP,
+                patrol: None,
                 weapon_cooldown: Timer::from_seconds(BOSS_WEAPON_LIFETIME, TimerMode::Once), // TODO
             },
         ));
 
-        // ボスの体力
+        // ボスの体力（位置はmove_boss_lifeでカメラに合わせる）
         for index in 1..=20 {
             commands.spawn((
                 OnGameScreen,
                 SpriteBundle {
                     texture: asset_server.load("images/status/life.png"),
-                    // 画面右上端から表示する。カメラを16pxずらしているのでややこしい
-                    transform: Transform::from_xyz(
-                        TILE_SIZE * (MAP_WIDTH_TILES - 1) as f32
-                            - TILE_SIZE / 2.
-                            - LIFE_SIZE / 2.
-                            - LIFE_SIZE * ((index - 1) % 10) as f32,
-                        480. - TILE_SIZE / 2.
-                            - LIFE_SIZE / 2.
-                            - (if index > 10 { LIFE_SIZE } else { 0. }),
-                        0.,
-                    ),
                     ..default()
                 },
-                BossLife { index },
+                BossLife {
+                    index,
+                    // 画面右上端から表示する
+                    offset: Vec2::new(
+                        320. - LIFE_SIZE / 2. - LIFE_SIZE * ((index - 1) % 10) as f32,
+                        240. - LIFE_SIZE / 2. - (if index > 10 { LIFE_SIZE } else { 0. }),
+                    ),
+                },
             ));
         }
     }
@@ -554,102 +1155,220 @@ pub mod game_scene {
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-        stage_state: Res<State<StageState>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+        progress: Res<CampaignProgress>,
     ) {
-        let spawn_position = match stage_state.get() {
-            StageState::Stage1 => STAGE1_ENEMY_POSITION.to_vec(),
-            StageState::Stage2 => STAGE2_ENEMY_POSITION.to_vec(),
-            StageState::Boss => vec![],
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Game");
+        // ボス戦からのリスポーンではザコ敵は出さない
+        let spawn_position = if progress.boss_checkpoint {
+            vec![]
+        } else {
+            stage.enemies.clone()
         };
 
-        for (i, position) in spawn_position.iter().enumerate() {
-            let kind = match i % 4 {
-                0 => EnemyKind::Slime,
-                1 => EnemyKind::Lizard,
-                2 => EnemyKind::RedDemon,
-                3 => EnemyKind::Wizard,
-                _ => EnemyKind::Slime,
-            };
-            let image = match kind {
-                EnemyKind::Slime => "images/character/slime.png",
-                EnemyKind::Lizard => "images/character/mohican_lizard.png",
-                EnemyKind::RedDemon => "images/character/red_demon.png",
-                EnemyKind::Wizard => "images/character/wizard.png",
-            };
-            let texture_handle = asset_server.load(image);
-            let texture_atlas = TextureAtlas::from_grid(
-                texture_handle,
-                Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
-                2,
-                1,
-                None,
-                None,
-            );
-            let texture_atlas_handle = texture_atlases.add(texture_atlas);
-            let animation_indices = AnimationIndices { first: 0, last: 1 };
-            let move_lifetime = match kind {
-                EnemyKind::Slime => 30, // TODO
-                _ => 20,
-            };
-            let walk_step = match kind {
-                EnemyKind::Lizard => ENEMY_RIZZARD_WALK_STEP,
-                _ => ENEMY_WALK_STEP,
-            };
-            commands.spawn((
+        for (index, position) in spawn_position.iter().enumerate() {
+            // 設定によっては倒した敵は復活させない
+            if current_stage.entry.keep_defeated_enemies
+                && progress.defeated_enemies.contains(&index)
+            {
+                continue;
+            }
+            spawn_enemy_character(
+                &mut commands,
+                &asset_server,
+                &mut texture_atlases,
+                stage,
+                position,
+                Some(index),
                 OnGameScreen,
-                SpriteSheetBundle {
-                    texture_atlas: texture_atlas_handle,
-                    sprite: TextureAtlasSprite::new(animation_indices.first),
-                    transform: Transform {
-                        translation: Vec3::new(
-                            TILE_SIZE * position.x as f32,
-                            TILE_SIZE * (14 - position.y) as f32,
-                            0.,
-                        ),
-                        scale: Vec3::new(-1., 1., 1.),
-                        ..default()
-                    },
+            );
+        }
+    }
+
+    // 敵を1体出す（エディタでも使う）
+    pub fn spawn_enemy_character(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlas>,
+        stage: &StageData,
+        position: &EnemyPosition,
+        index: Option<usize>,
+        marker: impl Bundle,
+    ) {
+        let kind = position.kind;
+        let texture_handle = asset_server.load(kind.image());
+        let texture_atlas = TextureAtlas::from_grid(
+            texture_handle,
+            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+            2,
+            1,
+            None,
+            None,
+        );
+        let texture_atlas_handle = texture_atlases.add(texture_atlas);
+        let animation_indices = AnimationIndices { first: 0, last: 1 };
+        let move_lifetime = position.move_lifetime.unwrap_or(kind.move_lifetime());
+        let move_duration = position.move_lifetime.unwrap_or(ENEMY_MOVE_LIFETIME);
+        let walk_step = position.walk_step.unwrap_or(match kind {
+            EnemyKind::Lizard => ENEMY_RIZZARD_WALK_STEP,
+            _ => ENEMY_WALK_STEP,
+        });
+        let (direction, scale_x) = match position.facing {
+            Facing::Left => (AllDirection::Left, 1.),
+            Facing::Right => (AllDirection::Right, -1.),
+        };
+        // 上下の指定がなければ出現した高さだけ
+        let patrol = position.patrol.map(|patrol| {
+            let top = patrol.top.unwrap_or(position.y);
+            let bottom = patrol.bottom.unwrap_or(position.y);
+            Rect::new(
+                TILE_SIZE * patrol.left as f32,
+                TILE_SIZE * stage.row_from_bottom(bottom) as f32,
+                TILE_SIZE * patrol.right as f32,
+                TILE_SIZE * stage.row_from_bottom(top) as f32,
+            )
+        });
+        commands.spawn((
+            marker,
+            SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle,
+                sprite: TextureAtlasSprite::new(animation_indices.first),
+                transform: Transform {
+                    translation: Vec3::new(
+                        TILE_SIZE * position.x as f32,
+                        TILE_SIZE * stage.row_from_bottom(position.y) as f32,
+                        0.,
+                    ),
+                    scale: Vec3::new(scale_x, 1., 1.),
                     ..default()
                 },
-                animation_indices,
-                AnimationTimer(Timer::from_seconds(0.33, TimerMode::Repeating)),
-                Character,
-                Enemy { kind },
-                EnemyCharacter {
-                    direction: AllDirection::Right,
-                    move_lifetime,
-                    walk_step,
-                    stop: false,
-                    weapon_cooldown: Timer::from_seconds(ENEMY_WEAPON_LIFETIME, TimerMode::Once),
-                },
-            ));
-        }
+                ..default()
+            },
+            animation_indices,
+            AnimationTimer(Timer::from_seconds(0.33, TimerMode::Repeating)),
+            Character,
+            Enemy { kind, index },
+            EnemyCharacter {
+                direction,
+                move_lifetime,
+                move_duration,
+                walk_step,
+                stop: false,
+                patrol,
+                weapon_cooldown: Timer::from_seconds(ENEMY_WEAPON_LIFETIME, TimerMode::Once),
+            },
+        ));
     }
 
     fn move_camera(
         query: Query<&Transform, (With<Player>, Without<Camera2d>)>,
         mut camera_query: Query<&mut Transform, With<Camera2d>>,
-        boss_state: Res<State<BossState>>,
+        camera_bounds: Res<CameraBounds>,
+        camera_lock: Res<CameraLock>,
     ) {
-        // ボス戦中は右端でカメラ固定
-        if boss_state.get() == &BossState::Active {
-            return;
-        }
-
         let player_transform = query.single();
         let mut transform = camera_query.single_mut();
+        if let Some((_, position)) = camera_lock.0 {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            return;
+        }
         transform.translation.x = player_transform
             .translation
             .x
-            .max(304.) // 320 - 32 / 2 (タイルの中心が0,0座標なため)
-            .min(TILE_SIZE * (MAP_WIDTH_TILES - 11) as f32 - 16.);
-        transform.translation.y = 224.; // 240 - 32 / 2
+            .clamp(camera_bounds.min.x, camera_bounds.max.x);
+        transform.translation.y = player_transform
+            .translation
+            .y
+            .clamp(camera_bounds.min.y, camera_bounds.max.y);
+    }
+
+    // 範囲を写すときのカメラの中心が動ける範囲
+    pub fn camera_bounds(stage: &StageData, area: TileArea) -> Rect {
+        let last = area.last();
+        // タイルの中心が座標になっているので半タイルずらす
+        let left = TILE_SIZE * area.x as f32 - TILE_SIZE / 2. + 320.;
+        let bottom = TILE_SIZE * stage.row_from_bottom(last.y) as f32 - TILE_SIZE / 2. + 240.;
+        // 画面より狭い範囲では左下に合わせる
+        let right = (TILE_SIZE * last.x as f32 + TILE_SIZE / 2. - 320.).max(left);
+        let top =
+            (TILE_SIZE * stage.row_from_bottom(area.y) as f32 + TILE_SIZE / 2. - 240.).max(bottom);
+        Rect::new(left, bottom, right, top)
+    }
+
+    fn move_parallax_layer(
+        mut layer_query: Query<(&mut Transform, &ParallaxLayer), Without<Camera2d>>,
+        camera_query: Query<&Transform, With<Camera2d>>,
+    ) {
+        let camera = camera_query.single().translation.truncate();
+        for (mut transform, layer) in layer_query.iter_mut() {
+            // scrollが1ならマップと一緒に動く（ずらさない）
+            let offset = (camera - layer.anchor) * (Vec2::ONE - layer.scroll);
+            transform.translation.x = offset.x;
+            transform.translation.y = offset.y;
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn move_player_life(
+        mut life_query: Query<
+            (&mut Transform, &mut Visibility, &PlayerLife),
+            (Without<Camera2d>, Without<Player>),
+        >,
+        camera_query: Query<&Transform, With<Camera2d>>,
+        player_query: Query<&Player>,
+    ) {
+        let camera_transform = camera_query.single();
+        let player = player_query.single();
+        for (mut transform, mut visibility, life) in life_query.iter_mut() {
+            transform.translation.x = camera_transform.translation.x + life.offset.x;
+            transform.translation.y = camera_transform.translation.y + life.offset.y;
+            // 減った分は表示しない
+            *visibility = if life.index <= player.life {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+
+    fn move_lives_icon(
+        mut icon_query: Query<(&mut Transform, &mut Visibility, &LivesIcon), Without<Camera2d>>,
+        camera_query: Query<&Transform, With<Camera2d>>,
+        progress: Res<CampaignProgress>,
+        editor: Option<Res<EditorSession>>,
+    ) {
+        let camera_transform = camera_query.single();
+        for (mut transform, mut visibility, icon) in icon_query.iter_mut() {
+            transform.translation.x = camera_transform.translation.x + icon.offset.x;
+            transform.translation.y = camera_transform.translation.y + icon.offset.y;
+            // テストプレイ中は残機がないので表示しない
+            *visibility = if icon.index <= progress.lives && editor.is_none() {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+
+    fn move_boss_life(
+        mut life_query: Query<(&mut Transform, &BossLife), Without<Camera2d>>,
+        camera_query: Query<&Transform, With<Camera2d>>,
+    ) {
+        let camera_transform = camera_query.single();
+        for (mut transform, life) in life_query.iter_mut() {
+            transform.translation.x = camera_transform.translation.x + life.offset.x;
+            transform.translation.y = camera_transform.translation.y + life.offset.y;
+        }
     }
 
     #[allow(clippy::type_complexity)]
     fn move_player_weapon_limit(
         mut background_query: Query<
-            &mut Transform,
+            (&mut Transform, &PlayerWeaponLimitStatus),
             (
                 With<PlayerWeaponLimitStatus>,
                 Without<Camera2d>,
@@ -657,7 +1376,7 @@ pub mod game_scene {
             ),
         >,
         mut number_query: Query<
-            &mut Transform,
+            (&mut Transform, &PlayerWeaponLimitStatusNumber),
             (
                 With<PlayerWeaponLimitStatusNumber>,
                 Without<Camera2d>,
@@ -668,11 +1387,13 @@ pub mod game_scene {
     ) {
         let camera_transform = camera_query.single();
         let x = camera_transform.translation.x - (320. - TILE_SIZE / 2.);
-        for mut transform in background_query.iter_mut() {
+        for (mut transform, status) in background_query.iter_mut() {
             transform.translation.x = x;
+            transform.translation.y = camera_transform.translation.y + status.offset_y;
         }
-        for mut transform in number_query.iter_mut() {
+        for (mut transform, status) in number_query.iter_mut() {
             transform.translation.x = x;
+            transform.translation.y = camera_transform.translation.y + status.offset_y;
         }
     }
 
@@ -696,40 +1417,316 @@ pub mod game_scene {
         }
     }
 
-    fn check_stage1_clear_system(
-        mut game_state: ResMut<NextState<GameState>>,
-        mut stage_state: ResMut<NextState<StageState>>,
-        mut query: Query<&Transform, With<Player>>,
+    fn animate_tiles(
+        time: Res<Time>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut query: Query<(
+            &AnimationIndices,
+            &mut AnimationTimer,
+            &mut AnimatedTiles,
+            &Mesh2dHandle,
+        )>,
     ) {
-        let transform = query.single_mut();
-        if transform.translation.x > TILE_SIZE * (MAP_WIDTH_TILES - 2) as f32 {
-            stage_state.set(StageState::Stage2);
+        for (indices, mut timer, mut tiles, mesh) in &mut query {
+            timer.tick(time.delta());
+            if !timer.just_finished() {
+                continue;
+            }
+            tiles.frame = if tiles.frame == indices.last {
+                indices.first
+            } else {
+                tiles.frame + 1
+            };
+            if let Some(mesh) = meshes.get_mut(&mesh.0) {
+                *mesh = tiles.batch.mesh(TILE_SIZE, tiles.frame as u32);
+            }
+        }
+    }
+
+    // 次のステージへ進む。最後のステージならエンディングへ
+    fn clear_stage(
+        game_state: &mut NextState<GameState>,
+        progress: &mut CampaignProgress,
+        campaigns: &Assets<Campaign>,
+        current_campaign: &CurrentCampaign,
+        editor: Option<&EditorSession>,
+    ) {
+        // テストプレイ中ならエディタに戻る
+        if editor.is_some() {
+            game_state.set(GameState::Editor);
+            return;
+        }
+        let campaign = campaigns
+            .get(&current_campaign.handle)
+            .expect("campaign is loaded before GameState::Game");
+        if progress.next_stage(campaign) {
             game_state.set(GameState::Loading);
+        } else {
+            game_state.set(GameState::Ending);
+        }
+    }
+
+    // プレイヤーがトリガーの範囲に出入りしたらイベントを送る
+    fn check_trigger_system(
+        player_query: Query<(&Transform, &Player), With<Player>>,
+        mut trigger_query: Query<(Entity, &Transform, &mut Trigger), Without<Player>>,
+        mut trigger_events: EventWriter<TriggerEvent>,
+    ) {
+        let (player_transform, player) = player_query.single();
+        if !player.live {
+            return;
+        }
+
+        let player_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+        for (entity, transform, mut trigger) in trigger_query.iter_mut() {
+            let overlapping = collide(
+                player_transform.translation,
+                player_size,
+                transform.translation,
+                trigger.size,
+            )
+            .is_some();
+            if overlapping && !trigger.inside && !(trigger.once && trigger.fired) {
+                trigger.inside = true;
+                trigger.fired = true;
+                trigger_events.send(TriggerEvent {
+                    trigger: entity,
+                    kind: TriggerEventKind::Enter,
+                });
+            } else if !overlapping && trigger.inside {
+                trigger.inside = false;
+                trigger_events.send(TriggerEvent {
+                    trigger: entity,
+                    kind: TriggerEventKind::Exit,
+                });
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn trigger_stage_system(
+        mut trigger_events: EventReader<TriggerEvent>,
+        trigger_query: Query<&Trigger>,
+        mut game_state: ResMut<NextState<GameState>>,
+        boss_state: Res<State<BossState>>,
+        mut next_boss_state: ResMut<NextState<BossState>>,
+        current_stage: Res<CurrentStage>,
+        mut progress: ResMut<CampaignProgress>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
+        editor: Option<Res<EditorSession>>,
+    ) {
+        for event in trigger_events.iter() {
+            if event.kind != TriggerEventKind::Enter {
+                continue;
+            }
+            let Ok(trigger) = trigger_query.get(event.trigger) else {
+                continue;
+            };
+            for action in trigger.actions.iter() {
+                match action {
+                    // ボスを倒すのがクリア条件ならゴールではクリアにならない
+                    TriggerAction::ClearStage
+                        if current_stage.entry.clear == ClearCondition::Goal =>
+                    {
+                        clear_stage(
+                            &mut game_state,
+                            &mut progress,
+                            &campaigns,
+                            &current_campaign,
+                            editor.as_deref(),
+                        );
+                    }
+                    TriggerAction::StartBoss if boss_state.get() == &BossState::InActive => {
+                        progress.boss_checkpoint = current_stage.entry.boss.is_some();
+                        next_boss_state.set(BossState::Active);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn trigger_camera_system(
+        mut trigger_events: EventReader<TriggerEvent>,
+        trigger_query: Query<&Trigger>,
+        mut camera_lock: ResMut<CameraLock>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+    ) {
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Game");
+        for event in trigger_events.iter() {
+            let Ok(trigger) = trigger_query.get(event.trigger) else {
+                continue;
+            };
+            for action in trigger.actions.iter() {
+                let TriggerAction::LockCamera { x, y } = action else {
+                    continue;
+                };
+                match event.kind {
+                    TriggerEventKind::Enter => {
+                        camera_lock.0 = Some((
+                            event.trigger,
+                            Vec2::new(
+                                TILE_SIZE * *x as f32,
+                                TILE_SIZE * stage.row_from_bottom(*y) as f32,
+                            ),
+                        ));
+                    }
+                    // 別のトリガーで固定し直していたらそのまま
+                    TriggerEventKind::Exit => {
+                        if camera_lock
+                            .0
+                            .is_some_and(|(entity, _)| entity == event.trigger)
+                        {
+                            camera_lock.0 = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn trigger_message_system(
+        mut commands: Commands,
+        mut trigger_events: EventReader<TriggerEvent>,
+        trigger_query: Query<&Trigger>,
+        message_query: Query<(Entity, &TriggerMessage)>,
+        asset_server: Res<AssetServer>,
+    ) {
+        for event in trigger_events.iter() {
+            match event.kind {
+                TriggerEventKind::Enter => {
+                    let Ok(trigger) = trigger_query.get(event.trigger) else {
+                        continue;
+                    };
+                    for action in trigger.actions.iter() {
+                        let TriggerAction::ShowMessage { image } = action else {
+                            continue;
+                        };
+                        // 位置はmove_trigger_messageでカメラに合わせる
+                        commands.spawn((
+                            OnGameScreen,
+                            SpriteBundle {
+                                texture: asset_server.load(image),
+                                transform: Transform::from_xyz(0., 0., 3.),
+                                ..default()
+                            },
+                            TriggerMessage {
+                                trigger: event.trigger,
+                            },
+                        ));
+                    }
+                }
+                TriggerEventKind::Exit => {
+                    for (entity, message) in message_query.iter() {
+                        if message.trigger == event.trigger {
+                            commands.entity(entity).despawn();
+                        }
+                    }
+                }
+            }
         }
     }
 
-    fn check_stage2_appear_boss_system(
-        mut stage_state: ResMut<NextState<StageState>>,
-        mut boss_state: ResMut<NextState<BossState>>,
-        mut query: Query<&Transform, With<Player>>,
+    fn move_trigger_message(
+        mut message_query: Query<&mut Transform, (With<TriggerMessage>, Without<Camera2d>)>,
+        camera_query: Query<&Transform, With<Camera2d>>,
     ) {
-        let transform = query.single_mut();
-        if transform.translation.x > TILE_SIZE * (MAP_WIDTH_TILES - 11) as f32 {
-            stage_state.set(StageState::Boss);
-            boss_state.set(BossState::Active);
+        let camera_transform = camera_query.single();
+        for mut transform in message_query.iter_mut() {
+            // 画面上部に表示する
+            transform.translation.x = camera_transform.translation.x;
+            transform.translation.y = camera_transform.translation.y + 160.;
+        }
+    }
+
+    fn trigger_spawn_enemy_system(
+        mut commands: Commands,
+        mut trigger_events: EventReader<TriggerEvent>,
+        trigger_query: Query<&Trigger>,
+        asset_server: Res<AssetServer>,
+        mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        stages: Res<Assets<StageData>>,
+        current_stage: Res<CurrentStage>,
+    ) {
+        let stage = stages
+            .get(&current_stage.handle)
+            .expect("stage is loaded before GameState::Game");
+        for event in trigger_events.iter() {
+            if event.kind != TriggerEventKind::Enter {
+                continue;
+            }
+            let Ok(trigger) = trigger_query.get(event.trigger) else {
+                continue;
+            };
+            for action in trigger.actions.iter() {
+                let TriggerAction::SpawnEnemies(enemies) = action else {
+                    continue;
+                };
+                for position in enemies.iter() {
+                    spawn_enemy_character(
+                        &mut commands,
+                        &asset_server,
+                        &mut texture_atlases,
+                        stage,
+                        position,
+                        None,
+                        OnGameScreen,
+                    );
+                }
+            }
         }
     }
 
     fn check_defeat_boss_system(
         mut game_state: ResMut<NextState<GameState>>,
         mut query: Query<&Boss, With<Boss>>,
+        mut progress: ResMut<CampaignProgress>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
+        current_stage: Res<CurrentStage>,
+        editor: Option<Res<EditorSession>>,
     ) {
         let boss = query.single_mut();
+        // ボスを倒したらクリア条件によらずステージクリア
         if boss.life <= 0 {
-            game_state.set(GameState::Ending);
+            // ボスによっては倒すと能力が使えるようになる
+            if let Some(ability) = current_stage
+                .entry
+                .boss
+                .and_then(|definition| definition.unlocks)
+            {
+                progress.abilities.unlock(ability);
+            }
+            clear_stage(
+                &mut game_state,
+                &mut progress,
+                &campaigns,
+                &current_campaign,
+                editor.as_deref(),
+            );
         }
     }
 
+    // ダメージを受けた後の無敵時間中は点滅する
+    fn player_flash_system(mut query: Query<(&mut Player, &mut TextureAtlasSprite), With<Player>>) {
+        let (mut player, mut texture) = query.single_mut();
+        player
+            .damage_cooldown
+            .tick(Duration::from_secs_f32(TIME_1F));
+        let alpha = if player.damage_cooldown.finished()
+            || (player.damage_cooldown.remaining_secs() / TIME_1F) % 10. <= 7.
+        {
+            1.
+        } else {
+            0.
+        };
+        texture.color.set_a(alpha);
+    }
+
     fn boss_flash_system(mut query: Query<(&Boss, &mut TextureAtlasSprite), With<Boss>>) {
         let (boss, mut texture) = query.single_mut();
         if boss.damage_cooldown.finished() {
@@ -766,18 +1763,20 @@ pub mod game_scene {
         }
     }
 
-    fn trigger_player_action_jump(
-        player: &mut Player,
-        transform: &mut Transform,
-        velocity: &mut Velocity,
-    ) {
+    fn trigger_player_action_jump(player: &mut Player, transform: &mut Transform) {
         player.grounded = false;
-        velocity.y = PLAYER_JUMP_FORCE;
-        player.jump_status.jump = true;
-        player.jump_status.jump_start_y = transform.translation.y;
-        player.jump_status.fall_time = 0.;
+        player.climb_status.climb = false;
+        player
+            .jump_status
+            .motion
+            .jump(transform.translation.y, PLAYER_JUMP_FORCE);
+        player.jump_status.buffer = 0;
+        player.jump_status.coyote = 0;
+        // ジャンプしたらダッシュは終わる
+        player.dash_status.frames = 0;
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn trigger_player_action_weapon(
         weapon_kind: PlayerWeaponKind,
         player: &mut Player,
@@ -787,6 +1786,7 @@ pub mod game_scene {
         asset_server: &Res<AssetServer>,
         mut texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
         mut commands: &mut Commands,
+        camera_transform: &Transform,
     ) {
         if weapon_query.iter().any(|weapon| weapon.kind == weapon_kind) {
             // すでに同じ武器を出しているなら何もしない
@@ -832,9 +1832,10 @@ pub mod game_scene {
             Direction::Left => Vec3::new(-1., 1., 0.),
         };
         let translation = match weapon_kind {
+            // 画面の一番上から落とす
             PlayerWeaponKind::Thunder => Vec3::new(
                 transform.translation.x,
-                TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                camera_transform.translation.y + (240. - TILE_SIZE / 2.),
                 // 壁よりも手前に表示
                 1.,
             ),
@@ -899,14 +1900,15 @@ pub mod game_scene {
         gamepads: Res<Gamepads>,
         button_inputs: Res<Input<GamepadButton>>,
         axes: Res<Axis<GamepadAxis>>,
-        mut query: Query<(&mut Player, &mut Transform, &mut Velocity), With<Player>>,
+        mut query: Query<(&mut Player, &mut Transform), (With<Player>, Without<Camera2d>)>,
         weapon_query: Query<&PlayerWeapon>,
         mut thunder_timer: ResMut<ThunderStopTimer>,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
         mut commands: Commands,
+        camera_query: Query<&Transform, With<Camera2d>>,
     ) {
-        let (mut player, mut transform, mut velocity) = query.single_mut();
+        let (mut player, mut transform) = query.single_mut();
         // デス中は何も受け付けない
         if !player.live {
             return;
@@ -918,25 +1920,57 @@ pub mod game_scene {
                 .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
                 .unwrap();
 
-            if left_stick_x < 0.
-                || button_inputs.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadLeft))
+            // 十字キーは全力、スティックは傾きに応じた速さで歩く
+            let walk = if button_inputs
+                .pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadLeft))
+            {
+                -1.
+            } else if button_inputs
+                .pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadRight))
             {
+                1.
+            } else {
+                stick_input(left_stick_x)
+            };
+            if walk < 0. {
                 transform.scale.x = -1.0;
                 player.direction = Direction::Left;
-                player.walk = true;
-            } else if left_stick_x > 0.
-                || button_inputs.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadRight))
-            {
+                player.walk = walk;
+            } else if walk > 0. {
                 transform.scale.x = 1.0;
                 player.direction = Direction::Right;
-                player.walk = true;
+                player.walk = walk;
             }
 
-            // Jump
-            if player.grounded
-                && button_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+            // Climb
+            let left_stick_y = axes
+                .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+                .unwrap();
+
+            if left_stick_y > 0.
+                || button_inputs.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadUp))
             {
-                trigger_player_action_jump(&mut player, &mut transform, &mut velocity);
+                player.climb_status.up = true;
+            } else if left_stick_y < 0.
+                || button_inputs.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadDown))
+            {
+                player.climb_status.down = true;
+            }
+
+            // Jump（跳ぶかどうかはcheck_collision_wall_systemで判断する）
+            let jump_button = GamepadButton::new(gamepad, GamepadButtonType::South);
+            if button_inputs.just_pressed(jump_button) {
+                player.jump_status.buffer = PLAYER_JUMP_BUFFER_FRAMES;
+            }
+            if button_inputs.pressed(jump_button) {
+                player.jump_status.pressed = true;
+            }
+
+            // Dash
+            if button_inputs
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger))
+            {
+                player.dash_status.pressed = true;
             }
 
             // Weapon
@@ -969,21 +2003,25 @@ pub mod game_scene {
                     &asset_server,
                     &mut texture_atlases,
                     &mut commands,
+                    camera_query.single(),
                 );
             }
         }
     }
 
+    #[allow(clippy::type_complexity)]
+    #[allow(clippy::too_many_arguments)]
     fn control_player_system(
         keyboard_input: Res<Input<KeyCode>>,
-        mut query: Query<(&mut Player, &mut Transform, &mut Velocity), With<Player>>,
+        mut query: Query<(&mut Player, &mut Transform), (With<Player>, Without<Camera2d>)>,
         weapon_query: Query<&PlayerWeapon>,
         mut thunder_timer: ResMut<ThunderStopTimer>,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
         mut commands: Commands,
+        camera_query: Query<&Transform, With<Camera2d>>,
     ) {
-        let (mut player, mut transform, mut velocity) = query.single_mut();
+        let (mut player, mut transform) = query.single_mut();
 
         // デス中は何も受け付けない
         if !player.live {
@@ -994,16 +2032,31 @@ pub mod game_scene {
         if keyboard_input.pressed(KeyCode::Left) {
             transform.scale.x = -1.0;
             player.direction = Direction::Left;
-            player.walk = true;
+            player.walk = -1.;
         } else if keyboard_input.pressed(KeyCode::Right) {
             transform.scale.x = 1.0;
             player.direction = Direction::Right;
-            player.walk = true;
+            player.walk = 1.;
+        }
+
+        // Climb
+        if keyboard_input.pressed(KeyCode::Up) {
+            player.climb_status.up = true;
+        } else if keyboard_input.pressed(KeyCode::Down) {
+            player.climb_status.down = true;
+        }
+
+        // Jump（跳ぶかどうかはcheck_collision_wall_systemで判断する）
+        if keyboard_input.just_pressed(KeyCode::X) {
+            player.jump_status.buffer = PLAYER_JUMP_BUFFER_FRAMES;
+        }
+        if keyboard_input.pressed(KeyCode::X) {
+            player.jump_status.pressed = true;
         }
 
-        // Jump
-        if player.grounded && keyboard_input.just_pressed(KeyCode::X) {
-            trigger_player_action_jump(&mut player, &mut transform, &mut velocity);
+        // Dash（使えるかどうかはcheck_collision_wall_systemで判断する）
+        if keyboard_input.just_pressed(KeyCode::C) {
+            player.dash_status.pressed = true;
         }
 
         // Weapon
@@ -1028,6 +2081,7 @@ pub mod game_scene {
                 &asset_server,
                 &mut texture_atlases,
                 &mut commands,
+                camera_query.single(),
             );
         }
     }
@@ -1045,12 +2099,11 @@ pub mod game_scene {
             ),
             With<Character>,
         >,
-        collider_query: Query<
-            (Entity, &Transform),
-            (With<Collider>, With<Wall>, Without<Character>),
-        >,
+        collision_map: Res<CollisionMap>,
+        platform_query: Query<(Entity, &DynamicPlatform)>,
         mut collision_events: EventWriter<CollisionEvent>,
         mut death_timer: ResMut<DeathTimer>,
+        stage_size: Res<StageSize>,
     ) {
         let (
             mut player_velocity,
@@ -1060,156 +2113,272 @@ pub mod game_scene {
             mut player_texture_atlas,
         ) = player_query.single_mut();
         let player_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
-        let tile_size = Vec2::new(TILE_SIZE, TILE_SIZE);
-        let mut next_time_translation = player_transform.translation;
-
-        // 横移動の判定
-        if player.walk {
-            next_time_translation.x = match player.direction {
-                Direction::Left => player_transform.translation.x - PLAYER_WALK_STEP,
-                Direction::Right => player_transform.translation.x + PLAYER_WALK_STEP,
-            };
-            // 画面外には移動できない
-            next_time_translation.x = next_time_translation.x.max(0.);
-
-            // 地面に接しているか検査
-            if player.grounded {
-                // TODO: 独自実装にすることで全Wallを判定しないようにする
-                let mut grounded_translation = player_transform.translation;
-                grounded_translation.y -= 1.;
-                grounded_translation.x = next_time_translation.x;
-
-                let mut fall_flag = true;
-                for (_collider_entity, transform) in &collider_query {
-                    let collision = collide(
-                        grounded_translation,
-                        player_size,
-                        transform.translation,
-                        tile_size,
-                    );
 
-                    // 接してる壁があるなら落ちない
-                    if collision.is_some() {
-                        collision_events.send_default();
-                        fall_flag = false;
-                    }
-                }
-                // 接してる壁がないなら落ちる
-                if fall_flag {
-                    player.grounded = false;
-                    player.jump_status.jump = false;
-                    player.jump_status.jump_start_y = player_transform.translation.y;
-                    player.jump_status.fall_time = 0.;
+        // 空中で接している壁の向き（左が-1、右が1、なければ0）
+        let wall = if player.abilities.wall_jump && !player.grounded && !player.climb_status.climb {
+            collision_map.wall_side(player_transform.translation, player_size)
+        } else {
+            0.
+        };
+        let walk = player.walk;
+
+        // 着地するか、はしごにつかまれば空中ジャンプと空中ダッシュは回復する
+        if player.grounded || player.climb_status.climb {
+            player.jump_status.air_jumps = PLAYER_AIR_JUMPS;
+            player.dash_status.air = true;
+        }
+
+        // ジャンプ。着地の少し前に押していても、足場から歩いて離れた少し後でも跳べる
+        let jump_pressed = player.jump_status.pressed;
+        player.jump_status.pressed = false;
+        if player.jump_status.buffer > 0 && player.live {
+            if player.grounded || player.climb_status.climb || player.jump_status.coyote > 0 {
+                trigger_player_action_jump(&mut player, &mut player_transform);
+            } else if wall != 0. {
+                // 壁ジャンプは壁と反対向きに跳ね返る
+                trigger_player_action_jump(&mut player, &mut player_transform);
+                player_velocity.x = -wall * PLAYER_WALL_JUMP_SPEED;
+                if wall > 0. {
+                    player.direction = Direction::Left;
+                    player_transform.scale.x = -1.0;
+                } else {
+                    player.direction = Direction::Right;
+                    player_transform.scale.x = 1.0;
                 }
+            } else if player.abilities.double_jump && player.jump_status.air_jumps > 0 {
+                player.jump_status.air_jumps -= 1;
+                trigger_player_action_jump(&mut player, &mut player_transform);
             }
-        };
+        }
+        player.jump_status.buffer = player.jump_status.buffer.saturating_sub(1);
+        player.jump_status.coyote = player.jump_status.coyote.saturating_sub(1);
+
+        // 上昇中にボタンを離したら、そこから弱い初速で跳び直す（押している長さで高さが変わる）
+        if !jump_pressed {
+            player
+                .jump_status
+                .motion
+                .release(player_transform.translation.y);
+        }
 
-        for (_collider_entity, transform) in &collider_query {
-            let collision = collide(
-                next_time_translation,
+        // ダッシュ。空中では着地するまでに1回だけ
+        let dash_pressed = player.dash_status.pressed;
+        player.dash_status.pressed = false;
+        player.dash_status.cooldown = player.dash_status.cooldown.saturating_sub(1);
+        if dash_pressed
+            && player.live
+            && player.abilities.dash
+            && player.dash_status.frames == 0
+            && player.dash_status.cooldown == 0
+            && !player.climb_status.climb
+            && (player.grounded || player.dash_status.air)
+        {
+            if !player.grounded {
+                player.dash_status.air = false;
+            }
+            player.dash_status.frames = PLAYER_DASH_FRAMES;
+            player.dash_status.cooldown = PLAYER_DASH_FRAMES + PLAYER_DASH_COOLDOWN_FRAMES;
+            player.dash_status.direction = match player.direction {
+                Direction::Left => -1.,
+                Direction::Right => 1.,
+            };
+        }
+
+        // 横移動量（入力に向けて加速し、離すと摩擦で止まる。ダッシュ中は一定の速さ）
+        let mut delta = Vec2::ZERO;
+        if player.dash_status.frames > 0 {
+            player.dash_status.frames -= 1;
+            player_velocity.x = player.dash_status.direction * PLAYER_DASH_SPEED;
+        } else {
+            player_velocity.x = walk_speed(player_velocity.x, player.walk, player.grounded);
+        }
+        player.walk = 0.;
+        let next_x = player_transform.translation.x + player_velocity.x;
+        // 画面外には移動できない
+        if next_x < 0. {
+            player_velocity.x = 0.;
+        }
+        delta.x = next_x.max(0.) - player_transform.translation.x;
+
+        player.support = None;
+
+        // はしごにつかまる（上は重なっているとき、下は足元にはしごがあるとき）
+        let climb_up = player.climb_status.up;
+        let climb_down = player.climb_status.down;
+        player.climb_status.up = false;
+        player.climb_status.down = false;
+        if !player.climb_status.climb && player.live {
+            let ladder_column = if climb_up {
+                collision_map.ladder_column(player_transform.translation, player_size)
+            } else if climb_down && player.grounded {
+                collision_map.ladder_column(player_transform.translation - Vec3::Y, player_size)
+            } else {
+                None
+            };
+            if let Some(column) = ladder_column {
+                player.climb_status.climb = true;
+                player.grounded = false;
+                player.jump_status.motion.jump = false;
+                player.dash_status.frames = 0;
+                player_velocity.0 = Vec2::ZERO;
+                // はしごの真ん中に合わせる
+                player_transform.translation.x = TILE_SIZE * column as f32;
+            }
+        }
+
+        // はしごの登り降り中は上下にだけ動かす
+        if player.climb_status.climb {
+            player_velocity.x = 0.;
+            let step = if climb_up {
+                PLAYER_CLIMB_STEP
+            } else if climb_down {
+                -PLAYER_CLIMB_STEP
+            } else {
+                0.
+            };
+            let sweep = collision_map.sweep_climbing(
+                player_transform.translation.truncate(),
                 player_size,
-                transform.translation,
-                tile_size,
+                Vec2::new(0., step),
             );
-            if let Some(collision) = collision {
+            if sweep.hit() {
                 collision_events.send_default();
-
-                match collision {
-                    // 左右なら止める
-                    Collision::Left | Collision::Right => {
-                        next_time_translation.x = player_transform.translation.x;
-                    }
-                    Collision::Top | Collision::Bottom | Collision::Inside => {}
-                }
             }
-        }
+            player_transform.translation.y = sweep.position.y;
 
-        // 左右への移動
-        if player.walk {
-            // 左右移動を反映
-            player_transform.translation.x = next_time_translation.x;
-            player.walk = false;
+            // 床に着いたか、はしごから離れたら登り降りをやめる
+            if sweep.hit_floor
+                || collision_map
+                    .ladder_column(player_transform.translation, player_size)
+                    .is_none()
+            {
+                player.climb_status.climb = false;
+                player.grounded =
+                    collision_map.is_on_floor(player_transform.translation, player_size);
+                player
+                    .jump_status
+                    .motion
+                    .fall_from(player_transform.translation.y);
+            }
+            return;
         }
 
-        // ジャンプ or 落下していなければこの先の判定をする必要はない
+        // 地面にいる間は横にだけ動かす（動く足場に乗っているなら一緒に動かす）
         if player.grounded {
+            if let Some((_, platform)) = platform_query.iter().find(|(_, platform)| {
+                platform.active
+                    && platform
+                        .support
+                        .carries(player_transform.translation.truncate(), player_size)
+            }) {
+                delta += platform.support.delta;
+            }
+
+            let sweep =
+                collision_map.sweep(player_transform.translation.truncate(), player_size, delta);
+            player_transform.translation.x = sweep.position.x;
+            player_transform.translation.y = sweep.position.y;
+            if sweep.hit_left || sweep.hit_right {
+                player_velocity.x = 0.;
+                player.dash_status.frames = 0;
+            }
+
+            // 接してる壁も足場もないなら落ちる
+            player.support = platform_query
+                .iter()
+                .find(|(_, platform)| {
+                    platform.active
+                        && platform
+                            .support
+                            .is_under(player_transform.translation.truncate(), player_size)
+                })
+                .map(|(entity, _)| entity);
+            if player.support.is_some()
+                || collision_map.is_on_floor(player_transform.translation, player_size)
+            {
+                collision_events.send_default();
+                return;
+            }
+            player.grounded = false;
+            player
+                .jump_status
+                .motion
+                .fall_from(player_transform.translation.y);
+            player.jump_status.coyote = PLAYER_COYOTE_FRAMES;
+            // このフレームはもう歩いたので、落ち始めるのは次のフレームから
             return;
         }
 
-        player_velocity.y -= GRAVITY * GRAVITY_TIME_STEP;
-        player.jump_status.fall_time += GRAVITY_TIME_STEP;
-
-        let t = player.jump_status.fall_time;
-        next_time_translation.y = if player.jump_status.jump {
-            player.jump_status.jump_start_y + PLAYER_JUMP_FORCE * t - 0.5 * GRAVITY * t * t
+        let y = player_transform.translation.y;
+        let mut next_y = if player.dash_status.frames > 0 {
+            // ダッシュ中は重力を受けずに真横に進む。終わったらそこから落ち始める
+            player.jump_status.motion.fall_from(y);
+            y
         } else {
-            player.jump_status.jump_start_y - 0.5 * GRAVITY * t * t
+            player.jump_status.motion.step()
         };
 
-        // 縦方向の判定
-        let is_fall = player_velocity.y < 0.;
-        let is_jump = player_velocity.y > 0.;
-        // TODO: collideだとどうしてもジャンプしながら壁にぶつかったときにTOPやBOTTOMが発生しておかしくなるので独自実装に切り替える
-        for (_collider_entity, transform) in &collider_query {
-            let collision = collide(
-                next_time_translation,
-                player_size,
-                transform.translation,
-                tile_size,
-            );
-            if let Some(collision) = collision {
-                collision_events.send_default();
+        // 壁に向かって押していると、ゆっくりずり落ちる
+        if wall != 0. && walk * wall > 0. {
+            next_y = player
+                .jump_status
+                .motion
+                .limit_fall(y, next_y, PLAYER_WALL_SLIDE_SPEED);
+        }
+        player_velocity.y = next_y - y;
+        delta.y = player_velocity.y;
 
-                if is_fall {
-                    match collision {
-                        // 落ちた先が壁なら下降をやめる
-                        Collision::Top | Collision::Inside | Collision::Left | Collision::Right => {
-                            player.grounded = true;
-                            player_velocity.y = 0.;
-
-                            // めり込まないように位置調整
-                            if next_time_translation.y % CHARACTER_SIZE != 0.0 {
-                                next_time_translation.y = next_time_translation.y
-                                    + (CHARACTER_SIZE - (next_time_translation.y % CHARACTER_SIZE));
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                if is_jump {
-                    match collision {
-                        // 壁の下側に頭を当てたら上昇をやめる
-                        Collision::Bottom
-                        | Collision::Inside
-                        | Collision::Left
-                        | Collision::Right => {
-                            player_velocity.y = 0.;
-                            player.jump_status.jump = false;
-                            player.jump_status.fall_time = 0.;
-
-                            // めり込まないように位置調整
-                            if next_time_translation.y % CHARACTER_SIZE != 0.0 {
-                                next_time_translation.y = next_time_translation.y
-                                    - (next_time_translation.y % CHARACTER_SIZE);
-                            }
-                            player.jump_status.jump_start_y = next_time_translation.y
-                        }
-                        _ => {}
-                    }
+        // 横→縦の順に壁の手前まで動かす
+        let sweep =
+            collision_map.sweep(player_transform.translation.truncate(), player_size, delta);
+        if sweep.hit() {
+            collision_events.send_default();
+        }
+        if sweep.hit_left || sweep.hit_right {
+            player_velocity.x = 0.;
+            player.dash_status.frames = 0;
+        }
+        let mut next_time_translation = sweep.position.extend(player_transform.translation.z);
+
+        // 動く足場、崩れる足場に上から着地したか（壁より高い位置にあるものを優先）
+        for (entity, platform) in platform_query.iter() {
+            if !platform.active {
+                continue;
+            }
+            if let Some(y) = platform.support.landing(
+                player_transform.translation.truncate(),
+                sweep.position,
+                player_size,
+            ) {
+                if y >= next_time_translation.y {
+                    collision_events.send_default();
+                    next_time_translation.y = y;
+                    player.support = Some(entity);
                 }
             }
         }
 
+        // 落ちた先が壁か足場なら下降をやめる
+        if sweep.hit_floor || player.support.is_some() {
+            player.grounded = true;
+            player_velocity.y = 0.;
+        }
+        // 壁の下側に頭を当てたら上昇をやめる
+        if sweep.hit_ceiling {
+            player_velocity.y = 0.;
+            player.jump_status.motion.fall_from(next_time_translation.y);
+        }
+
         // 上部の画面外にジャンプしようとしたら天井にぶつかったときと同じ処理にする
-        if next_time_translation.y >= TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32 {
+        let ceiling = TILE_SIZE * (stage_size.height - 1) as f32;
+        if next_time_translation.y >= ceiling {
             player_velocity.y = 0.;
-            player.jump_status.jump = false;
-            player.jump_status.fall_time = 0.;
-            next_time_translation.y = TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32;
-            player.jump_status.jump_start_y = next_time_translation.y
+            next_time_translation.y = ceiling;
+            player.jump_status.motion.fall_from(ceiling);
         }
 
         // 移動を反映
+        player_transform.translation.x = next_time_translation.x;
         player_transform.translation.y = next_time_translation.y;
 
         // 落ちたときはデス処理
@@ -1225,12 +2394,89 @@ pub mod game_scene {
         }
     }
 
+    // チェックポイントに触れたらリスポーン位置を更新する
+    fn check_checkpoint_system(
+        player_query: Query<(&Transform, &Player), With<Player>>,
+        mut checkpoint_query: Query<(&Transform, &Checkpoint, &mut Sprite), Without<Player>>,
+        mut progress: ResMut<CampaignProgress>,
+    ) {
+        let (player_transform, player) = player_query.single();
+        if !player.live {
+            return;
+        }
+
+        let size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+        let touched = checkpoint_query
+            .iter()
+            .find_map(|(transform, checkpoint, _)| {
+                collide(
+                    player_transform.translation,
+                    size,
+                    transform.translation,
+                    size,
+                )
+                .map(|_| checkpoint.index)
+            });
+        let Some(touched) = touched else {
+            return;
+        };
+        if progress.checkpoint == Some(touched) {
+            return;
+        }
+
+        progress.checkpoint = Some(touched);
+        for (_, checkpoint, mut sprite) in checkpoint_query.iter_mut() {
+            sprite.color = if checkpoint.index == touched {
+                CHECKPOINT_REACHED_COLOR
+            } else {
+                CHECKPOINT_COLOR
+            };
+        }
+    }
+
+    // トゲに触れたらデス
+    #[allow(clippy::type_complexity)]
+    fn check_collision_hazard_system(
+        mut player_query: Query<
+            (
+                &mut Transform,
+                &mut Player,
+                &mut AnimationIndices,
+                &mut TextureAtlasSprite,
+            ),
+            With<Player>,
+        >,
+        collision_map: Res<CollisionMap>,
+        mut collision_events: EventWriter<CollisionEvent>,
+        mut death_timer: ResMut<DeathTimer>,
+    ) {
+        let (mut player_transform, mut player, mut player_animation, mut player_texture_atlas) =
+            player_query.single_mut();
+        if !player.live {
+            return;
+        }
+
+        let player_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+        if collision_map.overlaps_hazard(player_transform.translation, player_size) {
+            collision_events.send_default();
+            die(
+                &mut player,
+                &mut player_transform,
+                &mut player_animation,
+                &mut player_texture_atlas,
+                &mut death_timer,
+                false,
+            );
+        }
+    }
+
     #[allow(clippy::type_complexity)]
     fn check_collision_enemy_system(
         mut player_query: Query<
             (
                 &mut Transform,
                 &mut Player,
+                &mut Velocity,
                 &mut AnimationIndices,
                 &mut TextureAtlasSprite,
             ),
@@ -1241,8 +2487,13 @@ pub mod game_scene {
         mut timer: ResMut<DeathTimer>,
     ) {
         let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
-        let (mut player_transform, mut player, mut player_animation, mut player_texture_atlas) =
-            player_query.single_mut();
+        let (
+            mut player_transform,
+            mut player,
+            mut player_velocity,
+            mut player_animation,
+            mut player_texture_atlas,
+        ) = player_query.single_mut();
 
         // 自分と敵の接触判定
         for (_enemy_entity, enemy_transform) in &enemy_query {
@@ -1252,15 +2503,16 @@ pub mod game_scene {
                 enemy_transform.translation,
                 character_size,
             );
-            if collision.is_some() && player.live {
+            if collision.is_some() && player.live && player.damage_cooldown.finished() {
                 collision_events.send_default();
-                die(
+                hit_player(
                     &mut player,
                     &mut player_transform,
+                    &mut player_velocity,
                     &mut player_animation,
                     &mut player_texture_atlas,
                     &mut timer,
-                    false,
+                    enemy_transform.translation,
                 );
             }
         }
@@ -1270,7 +2522,7 @@ pub mod game_scene {
     #[allow(clippy::type_complexity)]
     fn check_collision_player_weapon_system(
         mut commands: Commands,
-        enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+        enemy_query: Query<(Entity, &Transform, &Enemy), With<Enemy>>,
         mut player_weapon_query: Query<
             (Entity, &mut Transform, &mut PlayerWeapon),
             (
@@ -1283,6 +2535,7 @@ pub mod game_scene {
         camera_query: Query<&Transform, With<Camera2d>>,
         mut collision_events: EventWriter<CollisionEvent>,
         asset_server: Res<AssetServer>,
+        mut progress: ResMut<CampaignProgress>,
     ) {
         let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
         let camera_transform = camera_query.single();
@@ -1290,7 +2543,7 @@ pub mod game_scene {
         for (player_weapon_entity, player_weapon_transform, player_weapon) in
             &mut player_weapon_query
         {
-            for (enemy_entity, enemy_transform) in &enemy_query {
+            for (enemy_entity, enemy_transform, enemy) in &enemy_query {
                 // カメラ外の敵に攻撃判定はしない
                 if !is_inner_camera(camera_transform.translation, enemy_transform.translation) {
                     continue;
@@ -1310,6 +2563,9 @@ pub mod game_scene {
                         commands.entity(player_weapon_entity).despawn();
                     }
                     commands.entity(enemy_entity).despawn();
+                    if let Some(index) = enemy.index {
+                        progress.defeated_enemies.insert(index);
+                    }
 
                     // 20%の確率で武器を回復させるアイテムをドロップする
                     let mut rng = rand::thread_rng();
@@ -1354,6 +2610,7 @@ pub mod game_scene {
             (
                 &mut Transform,
                 &mut Player,
+                &mut Velocity,
                 &mut AnimationIndices,
                 &mut TextureAtlasSprite,
             ),
@@ -1365,8 +2622,13 @@ pub mod game_scene {
     ) {
         let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
         let boss_size = Vec2::new(BOSS_SIZE, BOSS_SIZE);
-        let (mut player_transform, mut player, mut player_animation, mut player_texture_atlas) =
-            player_query.single_mut();
+        let (
+            mut player_transform,
+            mut player,
+            mut player_velocity,
+            mut player_animation,
+            mut player_texture_atlas,
+        ) = player_query.single_mut();
 
         // 自分とボスの接触判定
         let boss_transform = boss_query.single();
@@ -1376,15 +2638,16 @@ pub mod game_scene {
             boss_transform.translation,
             boss_size,
         );
-        if collision.is_some() && player.live {
+        if collision.is_some() && player.live && player.damage_cooldown.finished() {
             collision_events.send_default();
-            die(
+            hit_player(
                 &mut player,
                 &mut player_transform,
+                &mut player_velocity,
                 &mut player_animation,
                 &mut player_texture_atlas,
                 &mut timer,
-                false,
+                boss_transform.translation,
             );
         }
     }
@@ -1531,6 +2794,7 @@ pub mod game_scene {
             (
                 &mut Transform,
                 &mut Player,
+                &mut Velocity,
                 &mut AnimationIndices,
                 &mut TextureAtlasSprite,
             ),
@@ -1544,8 +2808,13 @@ pub mod game_scene {
         mut death_timer: ResMut<DeathTimer>,
     ) {
         let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
-        let (mut player_transform, mut player, mut player_animation, mut player_texture_atlas) =
-            player_query.single_mut();
+        let (
+            mut player_transform,
+            mut player,
+            mut player_velocity,
+            mut player_animation,
+            mut player_texture_atlas,
+        ) = player_query.single_mut();
 
         // デス中なら衝突判定を行わない
         if !player.live {
@@ -1553,22 +2822,23 @@ pub mod game_scene {
         }
 
         for enemy_weapon_transform in &mut enemy_weapon_query {
-            // プレイヤーに当たったら死亡処理
+            // プレイヤーに当たったらダメージ（無敵時間中は当たらない）
             let collision = collide(
                 enemy_weapon_transform.translation,
                 character_size,
                 player_transform.translation,
                 character_size,
             );
-            if collision.is_some() {
+            if collision.is_some() && player.live && player.damage_cooldown.finished() {
                 collision_events.send_default();
-                die(
+                hit_player(
                     &mut player,
                     &mut player_transform,
+                    &mut player_velocity,
                     &mut player_animation,
                     &mut player_texture_atlas,
                     &mut death_timer,
-                    false,
+                    enemy_weapon_transform.translation,
                 );
             }
         }
@@ -1715,6 +2985,7 @@ pub mod game_scene {
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
         time: Res<Time>,
+        hazard_area: Res<BossHazardArea>,
     ) {
         let (mut boss_transform, mut enemy_charactor, boss) = boss_query.single_mut();
         let player_transform = player_query.single();
@@ -1726,7 +2997,7 @@ pub mod game_scene {
         enemy_charactor.move_lifetime -= 1;
         // 現在の行動時間（移動）が終了した時
         if enemy_charactor.move_lifetime == 0 {
-            enemy_charactor.move_lifetime = 40; // TODO
+            enemy_charactor.move_lifetime = enemy_charactor.move_duration;
 
             // 新たな動作の抽選を始める
             enemy_charactor.stop = false;
@@ -1789,35 +3060,31 @@ pub mod game_scene {
                 let translations = match kind {
                     BossWeaponKind::Meteor => [
                         Vec3::new(
-                            TILE_SIZE * 79. + TILE_SIZE * rng.gen_range(0..=20) as f32,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                            TILE_SIZE * rng.gen_range(hazard_area.columns.clone()) as f32,
+                            hazard_area.top,
                             3.,
                         ),
                         Vec3::new(
-                            TILE_SIZE * 79. + TILE_SIZE * rng.gen_range(0..=20) as f32,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                            TILE_SIZE * rng.gen_range(hazard_area.columns.clone()) as f32,
+                            hazard_area.top,
                             3.,
                         ),
                         Vec3::new(
-                            TILE_SIZE * 79. + TILE_SIZE * rng.gen_range(0..=20) as f32,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                            TILE_SIZE * rng.gen_range(hazard_area.columns.clone()) as f32,
+                            hazard_area.top,
                             3.,
                         ),
                     ],
                     BossWeaponKind::DarkThunder => [
                         Vec3::new(
                             player_transform.translation.x - TILE_SIZE,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
-                            3.,
-                        ),
-                        Vec3::new(
-                            player_transform.translation.x,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                            hazard_area.top,
                             3.,
                         ),
+                        Vec3::new(player_transform.translation.x, hazard_area.top, 3.),
                         Vec3::new(
                             player_transform.translation.x + TILE_SIZE,
-                            TILE_SIZE * (MAP_HEIGHT_TILES - 1) as f32,
+                            hazard_area.top,
                             3.,
                         ),
                     ],
@@ -1926,7 +3193,7 @@ pub mod game_scene {
             enemy_charactor.move_lifetime -= 1;
             // 現在の行動時間（移動）が終了した時
             if enemy_charactor.move_lifetime == 0 {
-                enemy_charactor.move_lifetime = 30; // TODO
+                enemy_charactor.move_lifetime = enemy_charactor.move_duration;
 
                 // 飛ぶ敵か止まっていたら新たな動作の抽選を始める
                 // それ以外は行動を継続
@@ -2075,13 +3342,28 @@ pub mod game_scene {
             ),
             (With<EnemyCharacter>, Without<Player>, Without<Camera2d>),
         >,
-        wall_query: Query<&Transform, (With<Wall>, Without<EnemyCharacter>, Without<Camera2d>)>,
+        collision_map: Res<CollisionMap>,
+        platform_query: Query<&DynamicPlatform>,
         camera_query: Query<&Transform, With<Camera2d>>,
         mut collision_events: EventWriter<CollisionEvent>,
+        stage_size: Res<StageSize>,
     ) {
         let camera_transform = camera_query.single();
         for (mut enemy_transform, mut enemy_charactor, maybe_enemy, maybe_boss) in &mut enemy_query
         {
+            // 歩く敵は乗っている動く足場と一緒に動かす
+            if matches!(maybe_enemy, Some(enemy) if enemy.kind != EnemyKind::RedDemon) {
+                if let Some(platform) = platform_query.iter().find(|platform| {
+                    platform.active
+                        && platform.support.carries(
+                            enemy_transform.translation.truncate(),
+                            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+                        )
+                }) {
+                    enemy_transform.translation += platform.support.delta.extend(0.);
+                }
+            }
+
             // カメラ外の敵は動かさない
             if !is_inner_camera(camera_transform.translation, enemy_transform.translation) {
                 continue;
@@ -2105,60 +3387,55 @@ pub mod game_scene {
                 };
                 // 画面外
                 if next_time_translation.x < 0.
-                    || next_time_translation.x > TILE_SIZE * (MAP_WIDTH_TILES - 2) as f32
+                    || next_time_translation.x > TILE_SIZE * (stage_size.width as f32 - 2.)
                     || next_time_translation.y < 0.
-                    || next_time_translation.y > TILE_SIZE * 14.
+                    || next_time_translation.y > TILE_SIZE * (stage_size.height - 1) as f32
+                    // 決められた範囲の外
+                    || enemy_charactor
+                        .patrol
+                        .is_some_and(|patrol| !patrol.contains(next_time_translation.truncate()))
                 {
                     enemy_charactor.stop = true;
                     // 移動中止
                     next_time_translation = enemy_transform.translation;
                 } else {
                     // 壁判定
-                    for wall_transform in &wall_query {
-                        let collision = collide(
-                            next_time_translation,
-                            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
-                            wall_transform.translation,
-                            Vec2::new(TILE_SIZE, TILE_SIZE),
-                        );
-                        if collision.is_some() {
-                            collision_events.send_default();
-                            enemy_charactor.stop = true;
-                            // 移動中止
-                            next_time_translation = enemy_transform.translation;
-                            break;
-                        }
+                    if collision_map.overlaps_wall(
+                        next_time_translation,
+                        Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+                    ) {
+                        collision_events.send_default();
+                        enemy_charactor.stop = true;
+                        // 移動中止
+                        next_time_translation = enemy_transform.translation;
                     }
                 }
 
-                // 飛ぶ敵以外は進む先に床がなければ停止させる
+                // 飛ぶ敵以外は進む先に床（足場を含む）がないかトゲがあれば停止させる
                 if let Some(enemy) = maybe_enemy {
                     if enemy.kind != EnemyKind::RedDemon {
-                        let mut exist_floor = false;
-                        for wall_transform in &wall_query {
-                            let mut check_floor_position = next_time_translation;
-                            check_floor_position.x = match enemy_charactor.direction {
-                                AllDirection::Left => {
-                                    enemy_transform.translation.x - CHARACTER_SIZE
-                                }
-                                AllDirection::Right => {
-                                    enemy_transform.translation.x + CHARACTER_SIZE
-                                }
-                                _ => enemy_transform.translation.x,
-                            };
-                            check_floor_position.y -= 1.;
-                            let collision = collide(
-                                check_floor_position,
-                                Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
-                                wall_transform.translation,
-                                Vec2::new(TILE_SIZE, TILE_SIZE),
-                            );
-                            if collision.is_some() {
-                                collision_events.send_default();
-                                exist_floor = true;
-                            }
-                        }
-                        if !exist_floor {
+                        let mut check_floor_position = next_time_translation;
+                        check_floor_position.x = match enemy_charactor.direction {
+                            AllDirection::Left => enemy_transform.translation.x - CHARACTER_SIZE,
+                            AllDirection::Right => enemy_transform.translation.x + CHARACTER_SIZE,
+                            _ => enemy_transform.translation.x,
+                        };
+                        let exist_floor = (collision_map.is_on_floor(
+                            check_floor_position,
+                            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+                        ) || platform_query.iter().any(|platform| {
+                            platform.active
+                                && platform.support.is_under(
+                                    check_floor_position.truncate(),
+                                    Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+                                )
+                        })) && !collision_map.overlaps_hazard(
+                            check_floor_position,
+                            Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+                        );
+                        if exist_floor {
+                            collision_events.send_default();
+                        } else {
                             enemy_charactor.stop = true;
                             // 移動中止
                             break;
@@ -2221,6 +3498,113 @@ pub mod game_scene {
         }
     }
 
+    // 残機アイテムに触れたら残機を増やす
+    fn check_collision_extra_life_item_system(
+        mut commands: Commands,
+        player_query: Query<(&Transform, &Player), With<Player>>,
+        item_query: Query<(Entity, &Transform, &ExtraLifeItem)>,
+        mut progress: ResMut<CampaignProgress>,
+        mut collision_events: EventWriter<CollisionEvent>,
+    ) {
+        let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+        let (player_transform, player) = player_query.single();
+
+        // デス中なら衝突判定を行わない
+        if !player.live {
+            return;
+        }
+
+        for (item_entity, item_transform, item) in item_query.iter() {
+            let collision = collide(
+                item_transform.translation,
+                character_size,
+                player_transform.translation,
+                character_size,
+            );
+            if collision.is_some() {
+                collision_events.send_default();
+                progress.lives = (progress.lives + 1).min(MAX_LIVES);
+                progress.extra_lives.insert(item.index);
+                commands.entity(item_entity).despawn();
+            }
+        }
+    }
+
+    // 能力のアイテムに触れたらその能力を使えるようにする
+    fn check_collision_ability_item_system(
+        mut commands: Commands,
+        mut player_query: Query<(&Transform, &mut Player), With<Player>>,
+        item_query: Query<(Entity, &Transform, &AbilityItem)>,
+        mut progress: ResMut<CampaignProgress>,
+        mut collision_events: EventWriter<CollisionEvent>,
+    ) {
+        let character_size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+        let (player_transform, mut player) = player_query.single_mut();
+
+        // デス中なら衝突判定を行わない
+        if !player.live {
+            return;
+        }
+
+        for (item_entity, item_transform, item) in item_query.iter() {
+            let collision = collide(
+                item_transform.translation,
+                character_size,
+                player_transform.translation,
+                character_size,
+            );
+            if collision.is_some() {
+                collision_events.send_default();
+                progress.abilities.unlock(item.ability);
+                player.abilities.unlock(item.ability);
+                commands.entity(item_entity).despawn();
+            }
+        }
+    }
+
+    // 敵、敵の武器、ボスに触れたときの処理
+    // 体力が残っていれば触れたものと反対側に飛ばされ、しばらく無敵になる。なくなったらデス
+    fn hit_player(
+        player: &mut Player,
+        transform: &mut Transform,
+        velocity: &mut Velocity,
+        animation_indices: &mut AnimationIndices,
+        texture_atlas_sprite: &mut TextureAtlasSprite,
+        timer: &mut ResMut<DeathTimer>,
+        source: Vec3,
+    ) {
+        player.life = player.life.saturating_sub(1);
+        if player.life == 0 {
+            die(
+                player,
+                transform,
+                animation_indices,
+                texture_atlas_sprite,
+                timer,
+                false,
+            );
+            return;
+        }
+        player.damage_cooldown.reset();
+
+        // ノックバック（小さく跳ねる）
+        let away = if transform.translation.x < source.x {
+            -1.
+        } else {
+            1.
+        };
+        velocity.x = PLAYER_KNOCKBACK_SPEED * away;
+        player.grounded = false;
+        player.support = None;
+        player.climb_status.climb = false;
+        // ボタンを離しても弱めない
+        player
+            .jump_status
+            .motion
+            .jump(transform.translation.y, PLAYER_KNOCKBACK_FORCE);
+        player.jump_status.motion.holding = false;
+    }
+
     // デス処理
     fn die(
         player: &mut Player,
@@ -2252,15 +3636,27 @@ pub mod game_scene {
         mut game_state: ResMut<NextState<GameState>>,
         time: Res<Time>,
         mut timer: ResMut<DeathTimer>,
+        editor: Option<Res<EditorSession>>,
+        mut progress: ResMut<CampaignProgress>,
     ) {
         let player = query.single();
         if !player.live && timer.tick(time.delta()).finished() {
-            game_state.set(GameState::Loading);
+            // テストプレイ中ならエディタに戻る（残機は減らさない）
+            if editor.is_some() {
+                game_state.set(GameState::Editor);
+            } else if progress.lives == 0 {
+                game_state.set(GameState::GameOver);
+            } else {
+                progress.lives -= 1;
+                game_state.set(GameState::Loading);
+            }
         }
     }
 
     fn is_inner_camera(camera_translation: Vec3, target_translation: Vec3) -> bool {
         target_translation.x >= camera_translation.x - 320. - 16.
             && target_translation.x < camera_translation.x + 320. + 16.
+            && target_translation.y >= camera_translation.y - 240. - 16.
+            && target_translation.y < camera_translation.y + 240. + 16.
     }
 }
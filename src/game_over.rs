@@ -0,0 +1,127 @@
+use try_rust_bevy::campaign::*;
+use try_rust_bevy::consts::*;
+use try_rust_bevy::utils::*;
+
+pub mod game_over_scene {
+    use bevy::prelude::*;
+
+    use super::{
+        despawn_screen, BossState, Campaign, CampaignProgress, CurrentCampaign, GameState,
+    };
+
+    // 選択肢の横に置くカーソルの位置（画像の文字に合わせる）
+    const CURSOR_X: f32 = -85.;
+    const CONTINUE_Y: f32 = -77.;
+    const TITLE_Y: f32 = -147.;
+
+    pub struct GameOverPlugin;
+
+    impl Plugin for GameOverPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::GameOver), game_over_setup)
+                .add_systems(Update, control_keys.run_if(in_state(GameState::GameOver)))
+                .add_systems(
+                    OnExit(GameState::GameOver),
+                    despawn_screen::<OnGameOverScreen>,
+                );
+        }
+    }
+
+    // シーン移動時にコンポーネントを消すためのタグとして使う
+    #[derive(Component)]
+    struct OnGameOverScreen;
+
+    #[derive(Component)]
+    struct GameOverCursor {
+        // trueならコンティニュー、falseならタイトルに戻る
+        continue_game: bool,
+    }
+
+    // デス中に押していたボタンで選んでしまわないように少し待つ
+    #[derive(Resource, Deref, DerefMut)]
+    struct SleepTimer(Timer);
+
+    fn game_over_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load("images/scene/game_over.png"),
+                sprite: Sprite::default(),
+                ..default()
+            },
+            OnGameOverScreen,
+        ));
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load("images/status/life.png"),
+                transform: Transform::from_xyz(CURSOR_X, CONTINUE_Y, 1.),
+                ..default()
+            },
+            GameOverCursor {
+                continue_game: true,
+            },
+            OnGameOverScreen,
+        ));
+
+        commands.insert_resource(SleepTimer(Timer::from_seconds(1.0, TimerMode::Once)));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn control_keys(
+        keyboard_input: Res<Input<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        button_inputs: Res<Input<GamepadButton>>,
+        time: Res<Time>,
+        mut timer: ResMut<SleepTimer>,
+        mut cursor_query: Query<(&mut Transform, &mut GameOverCursor)>,
+        mut game_state: ResMut<NextState<GameState>>,
+        mut progress: ResMut<CampaignProgress>,
+        mut boss_state: ResMut<NextState<BossState>>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
+    ) {
+        // タイマーを進める
+        timer.tick(time.delta());
+
+        let mut pressed = keyboard_input.just_pressed(KeyCode::Z);
+        let mut up = keyboard_input.just_pressed(KeyCode::Up);
+        let mut down = keyboard_input.just_pressed(KeyCode::Down);
+        for gamepad in gamepads.iter() {
+            if button_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South)) {
+                pressed = true;
+            }
+            if button_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadUp)) {
+                up = true;
+            }
+            if button_inputs.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadDown))
+            {
+                down = true;
+            }
+        }
+
+        let (mut transform, mut cursor) = cursor_query.single_mut();
+        if up || down {
+            cursor.continue_game = up;
+            transform.translation.y = if cursor.continue_game {
+                CONTINUE_Y
+            } else {
+                TITLE_Y
+            };
+        }
+
+        if pressed && timer.finished() {
+            // ボスの出現状態はどちらを選んでもリセットする
+            boss_state.set(BossState::default());
+            if cursor.continue_game {
+                // 今のステージを最初からやり直す
+                let campaign = campaigns
+                    .get(&current_campaign.handle)
+                    .expect("campaign is loaded before GameState::GameOver");
+                progress.restart_stage(campaign);
+                game_state.set(GameState::Loading);
+            } else {
+                *progress = CampaignProgress::default();
+                game_state.set(GameState::Title);
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::reachability::Reachability;
+use crate::stage::{
+    EnemyKind, EnemyPosition, Facing, StageData, StageFile, TileDefinition, TileKind, TilePosition,
+};
+
+// 作り直しても通れるステージにならなければ最後に穴のないステージを試す
+const MAX_ATTEMPTS: usize = 20;
+// 最初と最後の平らな部分の長さ
+const START_LENGTH: u32 = 6;
+const GOAL_LENGTH: u32 = 4;
+// 地面の高さ（下から数えた行数）
+const START_GROUND: u32 = 2;
+// 浮いている壁を置く地面からの高さ
+const BLOCK_HEIGHT: u32 = 3;
+
+// シードと難しさからステージを作るための設定
+#[derive(Clone, Debug)]
+pub struct GeneratorSettings {
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    // 0.0〜1.0。穴の多さと幅、段差、敵の数が増える
+    pub difficulty: f32,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            seed: 0,
+            width: 100,
+            height: 15,
+            difficulty: 0.5,
+        }
+    }
+}
+
+// ゴールまでたどり着けるステージを作る（同じ設定なら同じステージになる）
+// 穴のないステージでもたどり着けなければNone
+pub fn generate(settings: &GeneratorSettings) -> Option<StageData> {
+    generate_with_attempts(settings, MAX_ATTEMPTS)
+}
+
+fn generate_with_attempts(settings: &GeneratorSettings, attempts: usize) -> Option<StageData> {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    for attempt in 0..=attempts {
+        let stage = build(&mut rng, settings, attempt < attempts);
+        if Reachability::new(&stage).is_goal_reachable() {
+            return Some(stage);
+        }
+    }
+    None
+}
+
+fn build(rng: &mut StdRng, settings: &GeneratorSettings, gaps: bool) -> StageData {
+    let width = settings.width.max(START_LENGTH + GOAL_LENGTH + 8);
+    let height = settings.height.max(10);
+    let difficulty = settings.difficulty.clamp(0., 1.);
+    let max_gap = 1 + (difficulty * 3.).round() as u32;
+    let gap_chance = if gaps { 0.15 + 0.35 * difficulty } else { 0. };
+    let max_step: i32 = if difficulty > 0.5 { 2 } else { 1 };
+    let enemy_chance = 0.05 + 0.15 * difficulty;
+    let max_ground = height - BLOCK_HEIGHT - 3;
+
+    // 列ごとの地面の高さ（Noneは穴）と、浮いている壁の高さ
+    let mut ground: Vec<Option<u32>> = vec![Some(START_GROUND); width as usize];
+    let mut blocks: Vec<Option<u32>> = vec![None; width as usize];
+    let goal_start = width - 2 - GOAL_LENGTH;
+    let mut x = START_LENGTH;
+    let mut level = START_GROUND;
+    let mut after_gap = false;
+    while x < goal_start {
+        let remaining = goal_start - x;
+        if !after_gap && remaining > max_gap && rng.gen_bool(gap_chance as f64) {
+            let length = rng.gen_range(1..=max_gap);
+            for column in x..x + length {
+                ground[column as usize] = None;
+            }
+            x += length;
+            after_gap = true;
+            continue;
+        }
+
+        let length = rng.gen_range(2..=6).min(remaining);
+        let step = rng.gen_range(-max_step..=max_step);
+        level = (level as i32 + step).clamp(1, max_ground as i32) as u32;
+        let block = rng.gen_bool(0.2);
+        for column in x..x + length {
+            ground[column as usize] = Some(level);
+            if block {
+                blocks[column as usize] = Some(level + BLOCK_HEIGHT);
+            }
+        }
+        x += length;
+        after_gap = false;
+    }
+    for column in goal_start..width {
+        ground[column as usize] = Some(level);
+    }
+
+    // 上から数えた行に直す
+    let row = |ground: u32| height - 1 - ground;
+
+    let mut enemies = Vec::new();
+    for column in START_LENGTH..goal_start {
+        let Some(level) = ground[column as usize] else {
+            continue;
+        };
+        if rng.gen_bool(enemy_chance as f64) {
+            let kind = EnemyKind::ALL[rng.gen_range(0..EnemyKind::ALL.len())];
+            enemies.push(EnemyPosition {
+                x: column,
+                y: row(level),
+                kind,
+                facing: if rng.gen_bool(0.5) {
+                    Facing::Left
+                } else {
+                    Facing::Right
+                },
+                walk_step: None,
+                move_lifetime: None,
+                patrol: None,
+            });
+        }
+    }
+
+    // 真ん中に近い地面にチェックポイント
+    let checkpoints = (0..width / 2)
+        .flat_map(|offset| [width / 2 - offset, width / 2 + offset])
+        .find_map(|column| {
+            ground[column as usize].map(|level| TilePosition {
+                x: column,
+                y: row(level),
+            })
+        })
+        .into_iter()
+        .collect();
+
+    // 左端と右から2列目は壁（右端の1列はカメラに写らない）
+    let mut map = Vec::new();
+    for y in 0..height {
+        let level = height - 1 - y;
+        let line = (0..width)
+            .map(|column| {
+                let wall = column == 0
+                    || column == width - 2
+                    || ground[column as usize].is_some_and(|ground| level < ground)
+                    || blocks[column as usize] == Some(level);
+                if wall {
+                    'C'
+                } else if rng.gen_bool(0.05) {
+                    'B'
+                } else {
+                    'A'
+                }
+            })
+            .collect::<String>();
+        map.push(line);
+    }
+
+    let legend = HashMap::from([
+        (
+            'A',
+            TileDefinition {
+                kind: TileKind::Background,
+                image: "images/map/map_1.png".to_string(),
+                autotile: false,
+                animation: None,
+            },
+        ),
+        (
+            'B',
+            TileDefinition {
+                kind: TileKind::Background,
+                image: "images/map/map_2.png".to_string(),
+                autotile: false,
+                animation: None,
+            },
+        ),
+        (
+            'C',
+            TileDefinition {
+                kind: TileKind::Wall,
+                image: "images/map/map_3_auto.png".to_string(),
+                autotile: true,
+                animation: None,
+            },
+        ),
+    ]);
+
+    StageData::from_file(StageFile {
+        legend,
+        player_start: TilePosition {
+            x: 2,
+            y: row(START_GROUND),
+        },
+        enemies,
+        goal: Some(TilePosition {
+            x: width - 3,
+            y: row(level),
+        }),
+        checkpoints,
+        extra_lives: Vec::new(),
+        ability_items: Vec::new(),
+        triggers: Vec::new(),
+        boss_arena: None,
+        moving_platforms: Vec::new(),
+        crumbling_platforms: Vec::new(),
+        layers: Vec::new(),
+        map,
+    })
+    .expect("generated stage is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(seed: u64, difficulty: f32) -> GeneratorSettings {
+        GeneratorSettings {
+            seed,
+            difficulty,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_same_stage() {
+        let a = generate(&settings(7, 0.5)).unwrap();
+        let b = generate(&settings(7, 0.5)).unwrap();
+        assert_eq!(a.map, b.map);
+        assert_eq!(a.enemies.len(), b.enemies.len());
+    }
+
+    #[test]
+    fn different_seeds_generate_different_stages() {
+        let a = generate(&settings(1, 0.5)).unwrap();
+        let b = generate(&settings(2, 0.5)).unwrap();
+        assert_ne!(a.map, b.map);
+    }
+
+    #[test]
+    fn generated_stages_are_completable() {
+        for seed in 0..10 {
+            for difficulty in [0., 0.5, 1.] {
+                let stage = generate(&settings(seed, difficulty)).unwrap();
+                assert_eq!(stage.width(), 100);
+                assert_eq!(stage.height(), 15);
+                assert!(
+                    Reachability::new(&stage).is_goal_reachable(),
+                    "seed {} difficulty {}",
+                    seed,
+                    difficulty
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stage_without_gaps_is_completable() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let stage = build(&mut rng, &settings(3, 1.), false);
+        assert!(Reachability::new(&stage).is_goal_reachable());
+    }
+
+    #[test]
+    fn fallback_stage_is_checked() {
+        // 穴のあるステージを試さずに、最後の穴のないステージだけを作る
+        let stage = generate_with_attempts(&settings(3, 1.), 0).unwrap();
+        assert!(stage.map.last().unwrap().iter().all(|tile| *tile == 'C'));
+        assert!(Reachability::new(&stage).is_goal_reachable());
+    }
+
+    #[test]
+    fn generated_stage_loads_from_ron() {
+        // generate_stageで書き出したファイルはそのままステージとして読み込める
+        let stage = generate(&settings(4, 0.5)).unwrap();
+        let loaded = StageData::from_bytes(stage.to_ron().unwrap().as_bytes()).unwrap();
+        assert_eq!(loaded.map, stage.map);
+        assert_eq!(loaded.enemies.len(), stage.enemies.len());
+        assert_eq!(
+            loaded.goal.map(|goal| (goal.x, goal.y)),
+            stage.goal.map(|goal| (goal.x, goal.y))
+        );
+    }
+
+    #[test]
+    fn hard_stages_have_gaps() {
+        let stage = generate(&settings(5, 1.)).unwrap();
+        let bottom = stage.map.last().unwrap();
+        assert!(
+            bottom
+                .iter()
+                .filter(|tile| **tile == 'A' || **tile == 'B')
+                .count()
+                > 0
+        );
+    }
+}
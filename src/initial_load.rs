@@ -1,11 +1,13 @@
+use try_rust_bevy::campaign::*;
 use try_rust_bevy::consts::*;
 use try_rust_bevy::utils::*;
 
 // WebGLだとその画像が初めて表示されるときに画像ロードが始まって表示が遅れるので最初から全て読み込んでおくためのScene
 pub mod initial_load_scene {
+    use bevy::asset::LoadState;
     use bevy::prelude::*;
 
-    use super::{despawn_screen, GameState};
+    use super::{despawn_screen, CurrentCampaign, GameState, MAIN_CAMPAIGN};
 
     pub struct InitialLoadPlugin;
 
@@ -27,6 +29,11 @@ pub mod initial_load_scene {
     struct LoadingTimer(Timer);
 
     fn loading_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+        // ステージの並び順はタイトルより前に読み込んでおく
+        commands.insert_resource(CurrentCampaign {
+            handle: asset_server.load(MAIN_CAMPAIGN),
+        });
+
         // WebGL用のビルドでない場合は初期ロードがいらないので飛ばす
         if option_env!("WASM_BUILD").is_none() {
             commands.insert_resource(LoadingTimer(Timer::from_seconds(0., TimerMode::Once)));
@@ -97,7 +104,7 @@ pub mod initial_load_scene {
                 OnInitialLoadScreen,
             ));
         }
-        for file in ["life", "number"] {
+        for file in ["life", "number", "extra_life"] {
             commands.spawn((
                 SpriteBundle {
                     texture: asset_server.load(format!("images/status/{}.png", file)),
@@ -161,8 +168,18 @@ pub mod initial_load_scene {
         mut game_state: ResMut<NextState<GameState>>,
         time: Res<Time>,
         mut timer: ResMut<LoadingTimer>,
+        asset_server: Res<AssetServer>,
+        current_campaign: Res<CurrentCampaign>,
     ) {
-        if timer.tick(time.delta()).finished() {
+        // キャンペーンが読めなくてもタイトルには進む（始めようとしたときにエラーを出す）
+        let load_state = asset_server.get_load_state(&current_campaign.handle);
+        if load_state == LoadState::Failed {
+            error!("failed to load the campaign");
+            game_state.set(GameState::Title);
+            return;
+        }
+        let loaded = load_state == LoadState::Loaded;
+        if timer.tick(time.delta()).finished() && loaded {
             game_state.set(GameState::Title);
         }
     }
@@ -1,2 +1,12 @@
+pub mod campaign;
+pub mod collision;
 pub mod consts;
+pub mod generator;
+pub mod movement;
+pub mod preview;
+pub mod reachability;
+pub mod stage;
+pub mod tiled;
+pub mod tilemap;
 pub mod utils;
+pub mod validation;
@@ -1,10 +1,16 @@
+use try_rust_bevy::campaign::*;
 use try_rust_bevy::consts::*;
+use try_rust_bevy::stage::*;
 use try_rust_bevy::utils::*;
 
 pub mod loading_scene {
+    use bevy::asset::LoadState;
     use bevy::prelude::*;
 
-    use super::{despawn_screen, GameState};
+    use super::{
+        despawn_screen, Campaign, CampaignProgress, CurrentCampaign, CurrentStage, GameState,
+    };
+    use crate::editor::editor_scene::EditorSession;
 
     pub struct LoadingPlugin;
 
@@ -25,7 +31,13 @@ pub mod loading_scene {
     #[derive(Resource, Deref, DerefMut)]
     struct LoadingTimer(Timer);
 
-    fn loading_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    fn loading_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
+        progress: Res<CampaignProgress>,
+    ) {
         commands.spawn((
             SpriteBundle {
                 texture: asset_server.load("images/scene/scene_2.png"),
@@ -36,15 +48,48 @@ pub mod loading_scene {
         ));
 
         commands.insert_resource(LoadingTimer(Timer::from_seconds(0.5, TimerMode::Once)));
+
+        // キャンペーンの進行状況に合わせてステージ定義の読み込みを開始
+        let entry = campaigns
+            .get(&current_campaign.handle)
+            .expect("campaign is loaded before GameState::Loading")
+            .stage(&progress)
+            .clone();
+        commands.insert_resource(CurrentStage {
+            handle: asset_server.load(&entry.map),
+            entry,
+        });
     }
 
     fn countdown(
+        mut commands: Commands,
         mut game_state: ResMut<NextState<GameState>>,
         time: Res<Time>,
         mut timer: ResMut<LoadingTimer>,
+        asset_server: Res<AssetServer>,
+        current_stage: Res<CurrentStage>,
+        editor: Option<Res<EditorSession>>,
     ) {
-        if timer.tick(time.delta()).finished() {
-            game_state.set(GameState::StageTitle);
+        // ステージ定義が読めなければ（エラーの内容はアセットの読み込みで出ている）タイトルに戻る
+        let load_state = asset_server.get_load_state(&current_stage.handle);
+        if load_state == LoadState::Failed {
+            error!(
+                "{}: failed to load the stage, returning to the title",
+                current_stage.entry.map
+            );
+            commands.remove_resource::<EditorSession>();
+            game_state.set(GameState::Title);
+            return;
+        }
+
+        // ステージ定義の読み込みが終わるまでは待つ
+        let loaded = load_state == LoadState::Loaded;
+        if timer.tick(time.delta()).finished() && loaded {
+            if editor.is_some() {
+                game_state.set(GameState::Editor);
+            } else {
+                game_state.set(GameState::StageTitle);
+            }
         }
     }
 }
@@ -1,9 +1,13 @@
 use bevy::prelude::*;
+use try_rust_bevy::campaign::CampaignPlugin;
 use try_rust_bevy::consts::*;
+use try_rust_bevy::stage::StagePlugin;
 
 // 各シーン
+mod editor;
 mod ending;
 mod game;
+mod game_over;
 mod initial_load;
 mod loading;
 mod stage_title;
@@ -26,15 +30,18 @@ fn main() {
                     }),
                     ..default()
                 }),
+            StagePlugin,
+            CampaignPlugin,
             initial_load::initial_load_scene::InitialLoadPlugin,
             game::game_scene::GamePlugin,
             title::title_scene::TitlePlugin,
             loading::loading_scene::LoadingPlugin,
             stage_title::stage_title_scene::StageTitlePlugin,
             ending::ending_scene::EndingPlugin,
+            game_over::game_over_scene::GameOverPlugin,
+            editor::editor_scene::EditorPlugin,
         ))
         .add_state::<GameState>()
-        .add_state::<StageState>()
         .add_systems(Startup, setup)
         .run();
 }
@@ -0,0 +1,217 @@
+use crate::consts::*;
+use crate::stage::Ability;
+
+// 使えるようになった能力
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Abilities {
+    pub double_jump: bool,
+    pub dash: bool,
+    pub wall_jump: bool,
+}
+
+impl Abilities {
+    pub fn unlock(&mut self, ability: Ability) {
+        match ability {
+            Ability::DoubleJump => self.double_jump = true,
+            Ability::Dash => self.dash = true,
+            Ability::WallJump => self.wall_jump = true,
+        }
+    }
+
+    pub fn has(&self, ability: Ability) -> bool {
+        match ability {
+            Ability::DoubleJump => self.double_jump,
+            Ability::Dash => self.dash,
+            Ability::WallJump => self.wall_jump,
+        }
+    }
+}
+
+// 空中の縦の動き。跳んだ（落ち始めた）高さからの時間で高さを決める
+// ゲームのプレイヤーとReachabilityで同じ計算を使う
+#[derive(Clone, Copy, Debug)]
+pub struct JumpMotion {
+    // 初速があるか（なければ落ちるだけ）
+    pub jump: bool,
+    pub start_y: f32,
+    pub fall_time: f32,
+    // ジャンプの初速（上昇中にボタンを離すと弱める）
+    pub force: f32,
+    // ジャンプしてからボタンを押し続けている
+    pub holding: bool,
+}
+
+impl JumpMotion {
+    // yから落ち始める
+    pub fn falling(y: f32) -> Self {
+        JumpMotion {
+            jump: false,
+            start_y: y,
+            fall_time: 0.,
+            force: PLAYER_JUMP_FORCE,
+            holding: false,
+        }
+    }
+
+    // yから初速forceで跳ぶ
+    pub fn jump(&mut self, y: f32, force: f32) {
+        self.jump = true;
+        self.start_y = y;
+        self.fall_time = 0.;
+        self.force = force;
+        self.holding = true;
+    }
+
+    // 上昇中にボタンを離したら、そこから弱い初速で跳び直す（押している長さで高さが変わる）
+    pub fn release(&mut self, y: f32) {
+        if !self.holding {
+            return;
+        }
+        self.holding = false;
+        let speed = self.force - GRAVITY * self.fall_time;
+        if self.jump && speed > 0. {
+            self.force = speed * PLAYER_JUMP_CUT;
+            self.start_y = y;
+            self.fall_time = 0.;
+        }
+    }
+
+    // 上昇をやめてyから落ち直す（天井に頭を当てたとき、足場から離れたときなど）
+    pub fn fall_from(&mut self, y: f32) {
+        self.jump = false;
+        self.start_y = y;
+        self.fall_time = 0.;
+    }
+
+    // 1フレーム進めた高さ
+    pub fn step(&mut self) -> f32 {
+        self.fall_time += GRAVITY_TIME_STEP;
+        let t = self.fall_time;
+        if self.jump {
+            self.start_y + self.force * t - 0.5 * GRAVITY * t * t
+        } else {
+            self.start_y - 0.5 * GRAVITY * t * t
+        }
+    }
+
+    // yからnext_yへの落下を1フレームにspeedまでにする（壁をずり落ちるとき）
+    pub fn limit_fall(&mut self, y: f32, next_y: f32, speed: f32) -> f32 {
+        if next_y >= y - speed {
+            return next_y;
+        }
+        // 落下の式をこの速さのところからやり直す
+        let time = speed / (GRAVITY * GRAVITY_TIME_STEP);
+        self.jump = false;
+        self.fall_time = time;
+        self.start_y = y - speed + 0.5 * GRAVITY * time * time;
+        y - speed
+    }
+}
+
+// 横方向の速さ（1フレームの移動量）を入力に近づける
+// inputは-1から1の入力の強さ。逆向きの入力と入力なしは摩擦で減速する。空中では加速も減速も弱い
+pub fn walk_speed(speed: f32, input: f32, grounded: bool) -> f32 {
+    let target = input.clamp(-1., 1.) * PLAYER_WALK_STEP;
+    let accelerating = input != 0. && (speed == 0. || speed.signum() == input.signum());
+    let mut rate = if accelerating {
+        PLAYER_WALK_ACCELERATION
+    } else {
+        PLAYER_WALK_FRICTION
+    };
+    if !grounded {
+        rate *= PLAYER_AIR_CONTROL;
+    }
+    if speed < target {
+        (speed + rate).min(target)
+    } else {
+        (speed - rate).max(target)
+    }
+}
+
+// スティックの傾きを入力の強さにする（遊びの範囲は0、そこから端までを0から1に広げる）
+pub fn stick_input(value: f32) -> f32 {
+    let strength = (value.abs() - PLAYER_STICK_DEAD_ZONE) / (1. - PLAYER_STICK_DEAD_ZONE);
+    strength.clamp(0., 1.) * value.signum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accelerates_up_to_walk_step() {
+        let mut speed = 0.;
+        for _ in 0..100 {
+            speed = walk_speed(speed, 1., true);
+        }
+        assert_eq!(speed, PLAYER_WALK_STEP);
+        assert!(walk_speed(0., -1., true) < 0.);
+    }
+
+    #[test]
+    fn friction_stops_without_input() {
+        let mut speed = PLAYER_WALK_STEP;
+        for _ in 0..100 {
+            speed = walk_speed(speed, 0., true);
+        }
+        assert_eq!(speed, 0.);
+    }
+
+    #[test]
+    fn air_control_is_weaker() {
+        assert!(walk_speed(0., 1., false) < walk_speed(0., 1., true));
+        assert!(walk_speed(PLAYER_WALK_STEP, 0., false) > walk_speed(PLAYER_WALK_STEP, 0., true));
+    }
+
+    #[test]
+    fn releasing_jump_early_lowers_peak() {
+        let peak = |hold: usize| {
+            let mut motion = JumpMotion::falling(0.);
+            motion.jump(0., PLAYER_JUMP_FORCE);
+            let mut y = 0.;
+            let mut peak: f32 = 0.;
+            for frame in 0..120 {
+                if frame == hold {
+                    motion.release(y);
+                }
+                y = motion.step();
+                peak = peak.max(y);
+            }
+            peak
+        };
+        assert!(peak(4) < peak(120));
+    }
+
+    #[test]
+    fn limited_fall_keeps_that_speed() {
+        let mut motion = JumpMotion::falling(100.);
+        let mut y = 100.;
+        for _ in 0..60 {
+            let next_y = motion.step();
+            y = motion.limit_fall(y, next_y, 2.);
+        }
+        let next_y = motion.step();
+        assert!((y - motion.limit_fall(y, next_y, 2.) - 2.).abs() < 0.01);
+    }
+
+    #[test]
+    fn unlock_enables_only_that_ability() {
+        let mut abilities = Abilities::default();
+        abilities.unlock(Ability::Dash);
+        assert!(abilities.has(Ability::Dash));
+        assert!(!abilities.has(Ability::DoubleJump));
+        assert!(!abilities.has(Ability::WallJump));
+    }
+
+    #[test]
+    fn stick_input_scales_outside_dead_zone() {
+        assert_eq!(stick_input(PLAYER_STICK_DEAD_ZONE / 2.), 0.);
+        assert_eq!(stick_input(-1.), -1.);
+        let half = stick_input(0.5 + PLAYER_STICK_DEAD_ZONE / 2.);
+        assert!(half > 0. && half < 1.);
+        assert_eq!(
+            walk_speed(0., half, true),
+            PLAYER_WALK_ACCELERATION.min(half * PLAYER_WALK_STEP)
+        );
+    }
+}
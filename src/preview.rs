@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{crop_imm, flip_horizontal, overlay};
+use image::{Rgba, RgbaImage};
+
+use crate::stage::{EnemyPosition, Facing, StageData, TileArea, TriggerAction};
+use crate::tilemap::{layer_batches, tile_batches, TileBatch};
+
+// ゲームと同じ画像の大きさ（ピクセル）
+const TILE: u32 = 32;
+const BOSS: u32 = 64;
+// 目印の枠の色
+const PLAYER_COLOR: Rgba<u8> = Rgba([0, 255, 0, 255]);
+const GOAL_COLOR: Rgba<u8> = Rgba([255, 255, 0, 255]);
+const CHECKPOINT_COLOR: Rgba<u8> = Rgba([0, 255, 255, 255]);
+const TRIGGER_COLOR: Rgba<u8> = Rgba([255, 160, 0, 255]);
+const ARENA_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+const HAZARD_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+#[derive(Debug)]
+pub struct PreviewError {
+    pub path: PathBuf,
+    pub error: image::ImageError,
+}
+
+impl fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl std::error::Error for PreviewError {}
+
+// ステージ全体を1枚の画像にする（ウィンドウを開かずCPUだけで描く）
+// assetsは画像のパスの起点になるディレクトリ
+pub fn render_stage(stage: &StageData, assets: &Path) -> Result<RgbaImage, PreviewError> {
+    let mut preview = Preview {
+        assets,
+        images: HashMap::new(),
+        canvas: RgbaImage::new(TILE * stage.width() as u32, TILE * stage.height() as u32),
+    };
+
+    // マップと飾りのレイヤー（レイヤーはカメラが左下にあるときの位置）
+    // 壁より奥のものを先に描き、残りはキャラクターより手前に描く
+    let mut batches = tile_batches(stage, None);
+    for layer in stage.layers.iter() {
+        batches.extend(layer_batches(stage, layer));
+    }
+    batches.sort_by(|a, b| a.z.total_cmp(&b.z));
+    let (back, front): (Vec<TileBatch>, _) = batches.into_iter().partition(|batch| batch.z <= 0.);
+    for batch in back.iter() {
+        preview.draw_batch(stage, batch)?;
+    }
+
+    // 足場（動く足場は最初の位置）
+    let platforms = stage
+        .moving_platforms
+        .iter()
+        .filter_map(|platform| {
+            let first = platform.path.first()?;
+            Some((first.x, first.y, platform.width, &platform.image))
+        })
+        .chain(
+            stage
+                .crumbling_platforms
+                .iter()
+                .map(|platform| (platform.x, platform.y, platform.width, &platform.image)),
+        );
+    for (x, y, width, path) in platforms {
+        let image = preview.image(path)?;
+        for offset in 0..width.max(1) {
+            preview.draw(&image, TILE * (x + offset), TILE * y, 1.);
+        }
+    }
+
+    // 敵（トリガーで出現する敵は半透明）
+    for enemy in stage.enemies.iter() {
+        preview.draw_enemy(enemy, 1.)?;
+    }
+    for trigger in stage.triggers.iter() {
+        for action in trigger.actions.iter() {
+            if let TriggerAction::SpawnEnemies(enemies) = action {
+                for enemy in enemies.iter() {
+                    preview.draw_enemy(enemy, 0.5)?;
+                }
+            }
+        }
+        preview.outline(
+            TileArea {
+                x: trigger.x,
+                y: trigger.y,
+                width: trigger.width,
+                height: trigger.height,
+            },
+            TRIGGER_COLOR,
+        );
+    }
+
+    // ボス戦（出現する壁は半透明）
+    if let Some(arena) = &stage.boss_arena {
+        let wall = preview.image(&arena.wall_image)?;
+        for position in arena.walls.iter() {
+            preview.draw(&wall, TILE * position.x, TILE * position.y, 0.5);
+        }
+        let boss = preview.frame("images/character/boss.png", BOSS, 0)?;
+        // ゲームと同じくタイルの中心から半タイル上にずらす
+        preview.draw(
+            &boss,
+            (TILE * arena.boss.x + TILE / 2).saturating_sub(BOSS / 2),
+            (TILE * arena.boss.y).saturating_sub(BOSS / 2),
+            1.,
+        );
+        // ボス戦中のリスポーン位置はチェックポイントと同じ色
+        preview.outline(
+            tile_area(arena.respawn.x, arena.respawn.y),
+            CHECKPOINT_COLOR,
+        );
+        if let Some(camera) = arena.camera {
+            preview.outline(camera, ARENA_COLOR);
+        }
+        if let Some(hazard) = arena.hazard {
+            preview.outline(hazard, HAZARD_COLOR);
+        }
+    }
+
+    for extra_life in stage.extra_lives.iter() {
+        let image = preview.image("images/status/extra_life.png")?;
+        preview.draw(&image, TILE * extra_life.x, TILE * extra_life.y, 1.);
+    }
+
+    for item in stage.ability_items.iter() {
+        let image = preview.image(item.ability.image())?;
+        preview.draw(&image, TILE * item.x, TILE * item.y, 1.);
+    }
+
+    for checkpoint in stage.checkpoints.iter() {
+        preview.outline(tile_area(checkpoint.x, checkpoint.y), CHECKPOINT_COLOR);
+    }
+    if let Some(goal) = stage.goal {
+        preview.outline(tile_area(goal.x, goal.y), GOAL_COLOR);
+    }
+    let start = stage.player_start;
+    let player = preview.frame("images/character/char.png", TILE, 2)?;
+    preview.draw(&player, TILE * start.x, TILE * start.y, 1.);
+
+    for batch in front.iter() {
+        preview.draw_batch(stage, batch)?;
+    }
+    preview.outline(tile_area(start.x, start.y), PLAYER_COLOR);
+
+    Ok(preview.canvas)
+}
+
+fn tile_area(x: u32, y: u32) -> TileArea {
+    TileArea {
+        x,
+        y,
+        width: 1,
+        height: 1,
+    }
+}
+
+struct Preview<'a> {
+    assets: &'a Path,
+    // 読み込んだ画像（assetsからのパスごと）
+    images: HashMap<String, RgbaImage>,
+    canvas: RgbaImage,
+}
+
+impl<'a> Preview<'a> {
+    fn image(&mut self, path: &str) -> Result<RgbaImage, PreviewError> {
+        if let Some(image) = self.images.get(path) {
+            return Ok(image.clone());
+        }
+        let full_path = self.assets.join(path);
+        let image = image::open(&full_path)
+            .map_err(|error| PreviewError {
+                path: full_path,
+                error,
+            })?
+            .to_rgba8();
+        self.images.insert(path.to_string(), image.clone());
+        Ok(image)
+    }
+
+    // 横に並んだアニメーションのindex番目のコマ
+    fn frame(&mut self, path: &str, size: u32, index: u32) -> Result<RgbaImage, PreviewError> {
+        let image = self.image(path)?;
+        Ok(crop_imm(&image, size * index, 0, size, size).to_image())
+    }
+
+    // アニメーションは1コマ目
+    fn draw_batch(&mut self, stage: &StageData, batch: &TileBatch) -> Result<(), PreviewError> {
+        let image = self.image(&batch.tile.image)?;
+        let grid = batch.tile.grid();
+        let width = image.width() / grid.x;
+        let height = image.height() / grid.y;
+        for tile in batch.tiles.iter() {
+            // 下から数えた行なのでキャンバスの下端から上に置く
+            let Some(y) = (stage.height() - 1).checked_sub(tile.position.y as usize) else {
+                continue;
+            };
+            let part = crop_imm(&image, 0, height * tile.variant, width, height).to_image();
+            self.draw(&part, TILE * tile.position.x, TILE * y as u32, 1.);
+        }
+        Ok(())
+    }
+
+    fn draw_enemy(&mut self, enemy: &EnemyPosition, alpha: f32) -> Result<(), PreviewError> {
+        let mut image = self.frame(enemy.kind.image(), TILE, 0)?;
+        // 敵の画像は左向き
+        if enemy.facing == Facing::Right {
+            image = flip_horizontal(&image);
+        }
+        self.draw(&image, TILE * enemy.x, TILE * enemy.y, alpha);
+        Ok(())
+    }
+
+    // (x, y)は画像の左上のピクセル
+    fn draw(&mut self, image: &RgbaImage, x: u32, y: u32, alpha: f32) {
+        if alpha < 1. {
+            let mut image = image.clone();
+            for pixel in image.pixels_mut() {
+                pixel.0[3] = (pixel.0[3] as f32 * alpha) as u8;
+            }
+            overlay(&mut self.canvas, &image, x as i64, y as i64);
+        } else {
+            overlay(&mut self.canvas, image, x as i64, y as i64);
+        }
+    }
+
+    // タイルの範囲を2ピクセルの枠で囲む（キャンバスからはみ出した部分は描かない）
+    fn outline(&mut self, area: TileArea, color: Rgba<u8>) {
+        let (width, height) = self.canvas.dimensions();
+        let last = area.last();
+        let left = TILE * area.x;
+        let top = TILE * area.y;
+        if left >= width || top >= height {
+            return;
+        }
+        let right = (TILE * (last.x + 1)).min(width) - 1;
+        let bottom = (TILE * (last.y + 1)).min(height) - 1;
+        for x in left..=right {
+            for y in [top, (top + 1).min(bottom), bottom.saturating_sub(1), bottom] {
+                self.canvas.put_pixel(x, y, color);
+            }
+        }
+        for y in top..=bottom {
+            for x in [left, (left + 1).min(right), right.saturating_sub(1), right] {
+                self.canvas.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage::test_stage;
+
+    fn assets() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    #[test]
+    fn renders_whole_stage_with_markers() {
+        let stage = test_stage(
+            "player_start: (x: 1, y: 2), enemies: [], goal: (x: 3, y: 2),",
+            &[".....", ".....", ".....", "#####"],
+        );
+        let image = render_stage(&stage, &assets()).unwrap();
+
+        assert_eq!(image.dimensions(), (TILE * 5, TILE * 4));
+        // 枠はタイルの左上から描く
+        assert_eq!(*image.get_pixel(TILE, TILE * 2), PLAYER_COLOR);
+        assert_eq!(*image.get_pixel(TILE * 2 - 1, TILE * 3 - 1), PLAYER_COLOR);
+        assert_eq!(*image.get_pixel(TILE * 3, TILE * 2), GOAL_COLOR);
+        assert_eq!(*image.get_pixel(TILE * 4 - 1, TILE * 3 - 1), GOAL_COLOR);
+    }
+
+    #[test]
+    fn outline_is_clipped_to_canvas() {
+        let assets = assets();
+        let mut preview = Preview {
+            assets: &assets,
+            images: HashMap::new(),
+            canvas: RgbaImage::new(TILE * 2, TILE),
+        };
+        preview.outline(
+            TileArea {
+                x: 1,
+                y: 0,
+                width: 3,
+                height: 2,
+            },
+            ARENA_COLOR,
+        );
+        preview.outline(tile_area(0, 5), HAZARD_COLOR);
+        preview.outline(tile_area(5, 0), HAZARD_COLOR);
+
+        assert_eq!(*preview.canvas.get_pixel(TILE, 0), ARENA_COLOR);
+        assert_eq!(
+            *preview.canvas.get_pixel(TILE * 2 - 1, TILE - 1),
+            ARENA_COLOR
+        );
+        assert!(preview.canvas.pixels().all(|pixel| *pixel != HAZARD_COLOR));
+    }
+}
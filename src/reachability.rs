@@ -0,0 +1,504 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use bevy::sprite::collide_aabb::collide;
+
+use crate::collision::CollisionMap;
+use crate::consts::*;
+use crate::movement::{walk_speed, JumpMotion};
+use crate::stage::{StageData, TileArea, TriggerAction};
+
+// 空中にいられる最大フレーム数（これを超えたら落ちきったとみなす）
+const MAX_AIR_FRAMES: usize = 600;
+// ジャンプ中に横移動を押しているフレームの範囲の候補（終わりがNoneなら着地まで押し続ける）。
+// 真上に跳んでから横に押すと足場の裏に頭をぶつけずに上に乗れる
+const INPUT_WINDOWS: [(usize, Option<usize>); 11] = [
+    (0, Some(2)),
+    (0, Some(4)),
+    (0, Some(8)),
+    (0, Some(12)),
+    (0, Some(16)),
+    (0, Some(24)),
+    (0, Some(32)),
+    (0, None),
+    (8, None),
+    (12, None),
+    (16, None),
+];
+// ジャンプボタンを押しているフレーム数の候補（Noneは離さない）。小ジャンプなら天井に頭をぶつけにくい
+const JUMP_HOLDS: [Option<usize>; 2] = [None, Some(4)];
+
+// 空中の動きの途中で跳ぶ入力
+#[derive(Clone, Copy)]
+struct JumpInput {
+    // 何フレーム目に跳ぶか
+    frame: usize,
+    // 跳んでからボタンを押しているフレーム数
+    hold: Option<usize>,
+}
+
+impl JumpInput {
+    fn now(hold: Option<usize>) -> Option<Self> {
+        Some(JumpInput { frame: 0, hold })
+    }
+}
+
+// ステージのゴールまでたどり着けるかをゲームと同じジャンプの計算で調べる。
+// ジャンプはタイルの中心と足場の端（止まってから、または中心から走ってきて）からだけ試すので、
+// 実際より少し厳しめの判定になる。
+// 動く足場、崩れる足場、敵と、能力（2段ジャンプ、ダッシュ、壁ジャンプ）は考慮しない
+pub struct Reachability<'a> {
+    stage: &'a StageData,
+    map: CollisionMap,
+    size: Vec2,
+    ceiling: f32,
+    // 触れたらクリアになる範囲（中心, 大きさ）
+    goals: Vec<(Vec3, Vec2)>,
+}
+
+// 空中の動きの結果
+struct Flight {
+    landed: Option<Vec2>,
+    reached_goal: bool,
+}
+
+impl<'a> Reachability<'a> {
+    pub fn new(stage: &'a StageData) -> Self {
+        // ゲームと同じく、ゴールがあればゴール、なければ右端の列と、ClearStageのトリガー
+        let goal = match stage.goal {
+            Some(goal) => TileArea {
+                x: goal.x,
+                y: goal.y,
+                width: 1,
+                height: 1,
+            },
+            None => TileArea {
+                x: stage.width() as u32 - 1,
+                y: 0,
+                width: 1,
+                height: stage.height() as u32,
+            },
+        };
+        let triggers = stage
+            .triggers
+            .iter()
+            .filter(|trigger| {
+                trigger
+                    .actions
+                    .iter()
+                    .any(|action| matches!(action, TriggerAction::ClearStage))
+            })
+            .map(|trigger| TileArea {
+                x: trigger.x,
+                y: trigger.y,
+                width: trigger.width,
+                height: trigger.height,
+            });
+        let goals = [goal]
+            .into_iter()
+            .chain(triggers)
+            .map(|area| {
+                let last = area.last();
+                let width = last.x - area.x + 1;
+                let height = last.y - area.y + 1;
+                let bottom = stage.row_from_bottom(last.y);
+                (
+                    Vec3::new(
+                        TILE_SIZE * (area.x as f32 + (width - 1) as f32 / 2.),
+                        TILE_SIZE * (bottom as f32 + (height - 1) as f32 / 2.),
+                        0.,
+                    ),
+                    Vec2::new(TILE_SIZE * width as f32, TILE_SIZE * height as f32),
+                )
+            })
+            .collect();
+
+        Reachability {
+            stage,
+            map: CollisionMap::from_stage(stage, TILE_SIZE),
+            size: Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE),
+            ceiling: TILE_SIZE * (stage.height() - 1) as f32,
+            goals,
+        }
+    }
+
+    pub fn is_goal_reachable(&self) -> bool {
+        self.search().1
+    }
+
+    // プレイヤーが立てる位置（列, 下から数えた行）すべて
+    pub fn reachable_cells(&self) -> HashSet<(i32, i32)> {
+        self.search().0
+    }
+
+    fn search(&self) -> (HashSet<(i32, i32)>, bool) {
+        let mut cells = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        // 出現位置から落ちた先が最初の位置
+        let start = Vec2::new(
+            TILE_SIZE * self.stage.player_start.x as f32,
+            TILE_SIZE * self.stage.row_from_bottom(self.stage.player_start.y) as f32,
+        );
+        if self.reached_goal(start) {
+            return (cells, true);
+        }
+        let first = if self.map.is_on_floor(start.extend(0.), self.size) {
+            Flight {
+                landed: Some(start),
+                reached_goal: false,
+            }
+        } else {
+            self.fly(start, 0., None, |_| 0.)
+        };
+        if first.reached_goal {
+            return (cells, true);
+        }
+        if let Some(cell) = first.landed.and_then(|position| self.cell(position)) {
+            cells.insert(cell);
+            queue.push_back(cell);
+        }
+
+        while let Some(cell) = queue.pop_front() {
+            for flight in self.moves(cell) {
+                if flight.reached_goal {
+                    return (cells, true);
+                }
+                let Some(next) = flight.landed.and_then(|position| self.cell(position)) else {
+                    continue;
+                };
+                if cells.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        (cells, false)
+    }
+
+    fn position(&self, (column, row): (i32, i32)) -> Vec2 {
+        Vec2::new(TILE_SIZE * column as f32, TILE_SIZE * row as f32)
+    }
+
+    // 着地した位置から一番近い、タイルの中心で立てる位置
+    fn cell(&self, position: Vec2) -> Option<(i32, i32)> {
+        let row = (position.y / TILE_SIZE).round() as i32;
+        let nearest = (position.x / TILE_SIZE).round() as i32;
+        let other = if position.x >= TILE_SIZE * nearest as f32 {
+            nearest + 1
+        } else {
+            nearest - 1
+        };
+        [nearest, other]
+            .into_iter()
+            .find(|column| {
+                let center = self.position((*column, row)).extend(0.);
+                *column >= 0
+                    && !self.map.overlaps_wall(center, self.size)
+                    && !self.map.overlaps_hazard(center, self.size)
+                    && self.map.is_on_floor(center, self.size)
+            })
+            .map(|column| (column, row))
+    }
+
+    // 立っている位置からの移動（歩き、ジャンプ、はしご）
+    fn moves(&self, cell: (i32, i32)) -> Vec<Flight> {
+        let start = self.position(cell);
+        let mut flights = Vec::new();
+        for direction in [-1., 1.] {
+            flights.extend(self.walk(start, direction));
+            let (edge, speed) = self.edge(start, direction);
+            for (from, speed) in [(start, 0.), (edge, 0.), (edge, speed)] {
+                for hold in JUMP_HOLDS {
+                    for (press, release) in INPUT_WINDOWS {
+                        flights.push(self.fly(from, speed, JumpInput::now(hold), |frame| {
+                            if frame >= press
+                                && !matches!(release, Some(release) if frame >= release)
+                            {
+                                direction
+                            } else {
+                                0.
+                            }
+                        }));
+                    }
+                }
+            }
+        }
+        for hold in JUMP_HOLDS {
+            flights.push(self.fly(start, 0., JumpInput::now(hold), |_| 0.));
+        }
+        flights.extend(self.climb(start, 1.));
+        flights.extend(self.climb(start - Vec2::Y, -1.));
+        flights
+    }
+
+    // 止まった状態から1タイル分歩く。足場が途切れたらそのまま落ちる（押し続けるか離すか）
+    fn walk(&self, start: Vec2, direction: f32) -> Vec<Flight> {
+        let mut position = start;
+        let mut speed = 0.;
+        while (position.x - start.x).abs() < TILE_SIZE {
+            speed = walk_speed(speed, direction, true);
+            let next_x = (position.x + speed).max(0.);
+            let sweep = self
+                .map
+                .sweep(position, self.size, Vec2::new(next_x - position.x, 0.));
+            if sweep.position == position {
+                return vec![];
+            }
+            position = sweep.position;
+            if self.reached_goal(position) || self.overlaps_hazard(position) {
+                return vec![Flight {
+                    landed: None,
+                    reached_goal: self.reached_goal(position),
+                }];
+            }
+            if !self.map.is_on_floor(position.extend(0.), self.size) {
+                // 落ち始めてすぐ跳ぶか、ぎりぎりで跳ぶこともできる
+                let keep_walking = |_| direction;
+                let mut flights = vec![
+                    self.fly(position, speed, None, keep_walking),
+                    self.fly(position, speed, None, |_| 0.),
+                ];
+                for frame in [0, PLAYER_COYOTE_FRAMES - 1] {
+                    flights.push(self.fly(
+                        position,
+                        speed,
+                        Some(JumpInput { frame, hold: None }),
+                        keep_walking,
+                    ));
+                }
+                return flights;
+            }
+        }
+        vec![Flight {
+            landed: Some(position),
+            reached_goal: false,
+        }]
+    }
+
+    // 落ちずに歩いて行ける足場の端（1タイル未満）と、止まった状態から歩いて着いたときの速さ
+    fn edge(&self, start: Vec2, direction: f32) -> (Vec2, f32) {
+        let mut position = start;
+        let mut speed = 0.;
+        loop {
+            let next_speed = walk_speed(speed, direction, true);
+            let next = position + Vec2::new(next_speed, 0.);
+            if (next.x - start.x).abs() >= TILE_SIZE
+                || next.x < 0.
+                || self.map.overlaps_wall(next.extend(0.), self.size)
+                || !self.map.is_on_floor(next.extend(0.), self.size)
+            {
+                break;
+            }
+            position = next;
+            speed = next_speed;
+        }
+        (position, speed)
+    }
+
+    // はしごを登り降りしきった位置（下りは足元のはしごから）
+    fn climb(&self, start: Vec2, direction: f32) -> Option<Flight> {
+        let column = self.map.ladder_column(start.extend(0.), self.size)?;
+        let mut position = Vec2::new(TILE_SIZE * column as f32, start.y);
+        if self.map.overlaps_wall(position.extend(0.), self.size) {
+            return None;
+        }
+        loop {
+            let sweep = self.map.sweep_climbing(
+                position,
+                self.size,
+                Vec2::new(0., PLAYER_CLIMB_STEP * direction),
+            );
+            position = sweep.position;
+            if self.reached_goal(position) {
+                return Some(Flight {
+                    landed: None,
+                    reached_goal: true,
+                });
+            }
+            if sweep.hit()
+                || self
+                    .map
+                    .ladder_column(position.extend(0.), self.size)
+                    .is_none()
+            {
+                break;
+            }
+        }
+        if self.map.is_on_floor(position.extend(0.), self.size) {
+            Some(Flight {
+                landed: Some(position),
+                reached_goal: false,
+            })
+        } else {
+            Some(self.fly(position, 0., None, |_| 0.))
+        }
+    }
+
+    // ジャンプ、または落下を着地するまで1フレームずつ計算する（縦の動きはゲームと同じJumpMotion）
+    // speedは空中に出たときの横の速さ
+    fn fly(
+        &self,
+        start: Vec2,
+        speed: f32,
+        jump_input: Option<JumpInput>,
+        input: impl Fn(usize) -> f32,
+    ) -> Flight {
+        let mut position = start;
+        let mut speed = speed;
+        let mut motion = JumpMotion::falling(start.y);
+        for frame in 0..MAX_AIR_FRAMES {
+            if let Some(jump_input) = jump_input {
+                if frame == jump_input.frame {
+                    motion.jump(position.y, PLAYER_JUMP_FORCE);
+                }
+                if matches!(jump_input.hold, Some(hold) if frame >= jump_input.frame + hold) {
+                    motion.release(position.y);
+                }
+            }
+            let next_y = motion.step();
+            speed = walk_speed(speed, input(frame), false);
+            let next_x = position.x + speed;
+            if next_x < 0. {
+                speed = 0.;
+            }
+            let sweep = self.map.sweep(
+                position,
+                self.size,
+                Vec2::new(next_x.max(0.) - position.x, next_y - position.y),
+            );
+            position = sweep.position;
+            if sweep.hit_left || sweep.hit_right {
+                speed = 0.;
+            }
+
+            if sweep.hit_ceiling || position.y >= self.ceiling {
+                position.y = position.y.min(self.ceiling);
+                motion.fall_from(position.y);
+            }
+            if self.reached_goal(position) {
+                return Flight {
+                    landed: None,
+                    reached_goal: true,
+                };
+            }
+            // トゲに触れるか下に落ちたらデス
+            if self.overlaps_hazard(position) || position.y < 0. {
+                break;
+            }
+            if sweep.hit_floor {
+                return Flight {
+                    landed: Some(position),
+                    reached_goal: false,
+                };
+            }
+        }
+        Flight {
+            landed: None,
+            reached_goal: false,
+        }
+    }
+
+    fn overlaps_hazard(&self, position: Vec2) -> bool {
+        self.map.overlaps_hazard(position.extend(0.), self.size)
+    }
+
+    fn reached_goal(&self, position: Vec2) -> bool {
+        self.goals
+            .iter()
+            .any(|(center, size)| collide(position.extend(0.), self.size, *center, *size).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage::test_stage;
+
+    fn stage(map: &[&str]) -> StageData {
+        test_stage(
+            "player_start: (x: 2, y: 7), enemies: [], goal: (x: 17, y: 7),",
+            map,
+        )
+    }
+
+    #[test]
+    fn shipped_stages_are_completable() {
+        for source in [
+            include_str!("../assets/stages/stage1.stage.ron"),
+            include_str!("../assets/stages/stage2.stage.ron"),
+        ] {
+            let stage = StageData::from_bytes(source.as_bytes()).unwrap();
+            assert!(Reachability::new(&stage).is_goal_reachable());
+        }
+    }
+
+    #[test]
+    fn jumps_over_narrow_gap() {
+        let stage = stage(&[
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#########...########",
+            "#########...########",
+        ]);
+        assert!(Reachability::new(&stage).is_goal_reachable());
+    }
+
+    #[test]
+    fn cannot_jump_over_wide_gap() {
+        let stage = stage(&[
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#####..........#####",
+            "#####..........#####",
+        ]);
+        let reachability = Reachability::new(&stage);
+        assert!(!reachability.is_goal_reachable());
+        assert!(reachability.reachable_cells().iter().all(|(x, _)| *x < 5));
+    }
+
+    #[test]
+    fn cannot_climb_high_wall() {
+        let stage = stage(&[
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...........#.......",
+            "#...........#.......",
+            "#...........#.......",
+            "#...........#.......",
+            "####################",
+            "####################",
+        ]);
+        assert!(!Reachability::new(&stage).is_goal_reachable());
+    }
+
+    #[test]
+    fn jumps_onto_low_wall() {
+        let stage = stage(&[
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...................",
+            "#...........#.......",
+            "#...........#.......",
+            "####################",
+            "####################",
+        ]);
+        assert!(Reachability::new(&stage).is_goal_reachable());
+    }
+}
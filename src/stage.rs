@@ -0,0 +1,1011 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::campaign::CampaignStage;
+use crate::tiled::TiledLoader;
+
+// ステージ定義ファイル(assets/stages/*.stage.ron)とTiledのマップを読み込むためのプラグイン
+pub struct StagePlugin;
+
+impl Plugin for StagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<StageData>()
+            .init_asset_loader::<StageLoader>()
+            .init_asset_loader::<TiledLoader>();
+    }
+}
+
+// 現在プレイ中のステージ（Loading中に読み込みを開始する）
+#[derive(Resource)]
+pub struct CurrentStage {
+    pub handle: Handle<StageData>,
+    // キャンペーン上でのこのステージの設定
+    pub entry: CampaignStage,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum TileKind {
+    Background,
+    Wall,
+    // 下からすり抜けられて上に乗れる足場
+    Platform,
+    // 触れるとデス
+    Spike,
+    // 上下キーで登り降りできる
+    Ladder,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TileDefinition {
+    pub kind: TileKind,
+    pub image: String,
+    // 上下左右の同じ種類のタイルとのつながりで絵を変える（マップの外はつながっている扱い）
+    // imageは縦に16個並べた画像で、上、右、下、左がつながっていればそれぞれ1、2、4、8を足した番号の絵を使う
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub autotile: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<TileAnimation>,
+}
+
+// imageに横に並べたコマを順に表示する（水、松明など）
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TileAnimation {
+    pub frames: u32,
+    // 1コマの秒数
+    #[serde(default = "default_frame_time")]
+    pub frame_time: f32,
+}
+
+impl TileAnimation {
+    // コマがあり、1コマの秒数がタイマーにできる値か
+    fn is_valid(&self) -> bool {
+        self.frames > 0 && self.frame_time.is_finite() && self.frame_time > 0.
+    }
+}
+
+impl TileDefinition {
+    // 画像を何列・何行に分けて使うか
+    pub fn grid(&self) -> UVec2 {
+        UVec2::new(
+            self.animation
+                .map_or(1, |animation| animation.frames.max(1)),
+            if self.autotile { 16 } else { 1 },
+        )
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn default_frame_time() -> f32 {
+    0.25
+}
+
+// マップ上の位置（タイル単位、yは上から数える）
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TilePosition {
+    pub x: u32,
+    pub y: u32,
+}
+
+// マップ上の範囲（タイル単位、位置は左上のタイル）
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TileArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileArea {
+    // 右下のタイル
+    pub fn last(&self) -> TilePosition {
+        TilePosition {
+            x: self.x + self.width.max(1) - 1,
+            y: self.y + self.height.max(1) - 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum EnemyKind {
+    Slime,
+    Lizard,
+    Wizard,
+    RedDemon,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 4] = [
+        EnemyKind::Slime,
+        EnemyKind::Lizard,
+        EnemyKind::Wizard,
+        EnemyKind::RedDemon,
+    ];
+
+    // 横に2コマ並んだアニメーション画像
+    pub fn image(self) -> &'static str {
+        match self {
+            EnemyKind::Slime => "images/character/slime.png",
+            EnemyKind::Lizard => "images/character/mohican_lizard.png",
+            EnemyKind::RedDemon => "images/character/red_demon.png",
+            EnemyKind::Wizard => "images/character/wizard.png",
+        }
+    }
+
+    // 出現してから最初の行動を続けるフレーム数
+    pub fn move_lifetime(self) -> usize {
+        match self {
+            EnemyKind::Slime => 30,
+            _ => 20,
+        }
+    }
+}
+
+// アイテムやボスを倒すと使えるようになる動き
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Ability {
+    // 空中でもう1回ジャンプする
+    DoubleJump,
+    // 向いている方向に素早く移動する（空中では着地するまで1回）
+    Dash,
+    // 壁に向かって押しながら落ちると遅くなり、壁を蹴ってジャンプできる
+    WallJump,
+}
+
+impl Ability {
+    pub fn image(self) -> &'static str {
+        match self {
+            Ability::DoubleJump => "images/status/ability_double_jump.png",
+            Ability::Dash => "images/status/ability_dash.png",
+            Ability::WallJump => "images/status/ability_wall_jump.png",
+        }
+    }
+}
+
+// 取ると能力が使えるようになるアイテム
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct AbilityItemDefinition {
+    pub x: u32,
+    pub y: u32,
+    pub ability: Ability,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Facing {
+    Left,
+    #[default]
+    Right,
+}
+
+// 敵が動ける範囲（タイル単位、yは上から数える）。上下は飛ぶ敵のためのもの
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct PatrolBounds {
+    pub left: u32,
+    pub right: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bottom: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct EnemyPosition {
+    pub x: u32,
+    pub y: u32,
+    pub kind: EnemyKind,
+    #[serde(default)]
+    pub facing: Facing,
+    // 1フレームに進むピクセル数（省略すると敵の種類ごとの値）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub walk_step: Option<f32>,
+    // 1つの行動を続けるフレーム数（省略すると最初の行動だけ敵の種類ごとの値で、その後は30）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub move_lifetime: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patrol: Option<PatrolBounds>,
+}
+
+// 経路に沿って動く足場（位置は足場の左端のタイル）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MovingPlatformDefinition {
+    pub image: String,
+    // 横に並べるタイル数
+    #[serde(default = "default_platform_width")]
+    pub width: u32,
+    // 最初の位置から順に巡回し、最後まで行ったら最初に戻る
+    pub path: Vec<TilePosition>,
+    // 1フレームに進むピクセル数
+    #[serde(default = "default_platform_speed")]
+    pub speed: f32,
+}
+
+// 乗ると少しして崩れ、しばらくすると元に戻る足場（位置は足場の左端のタイル）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrumblingPlatformDefinition {
+    pub x: u32,
+    pub y: u32,
+    pub image: String,
+    #[serde(default = "default_platform_width")]
+    pub width: u32,
+    // 乗ってから崩れるまでの秒数
+    #[serde(default = "default_crumble_time")]
+    pub crumble_time: f32,
+    // 崩れてから元に戻るまでの秒数
+    #[serde(default = "default_respawn_time")]
+    pub respawn_time: f32,
+}
+
+// 範囲に入ったときに起こすこと
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TriggerAction {
+    // ステージクリア（ボスを倒すのがクリア条件のステージでは何もしない）
+    ClearStage,
+    StartBoss,
+    // 範囲内にいる間はカメラの中心をこの位置（タイル単位、yは上から数える）に固定
+    LockCamera { x: u32, y: u32 },
+    // 範囲内にいる間は画像を画面上部に表示
+    ShowMessage { image: String },
+    // 範囲に入ったら敵を出現させる
+    SpawnEnemies(Vec<EnemyPosition>),
+}
+
+// プレイヤーが出入りしたときにactionsを実行する範囲（位置は左上のタイル）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TriggerDefinition {
+    pub x: u32,
+    pub y: u32,
+    #[serde(default = "default_trigger_size")]
+    pub width: u32,
+    #[serde(default = "default_trigger_size")]
+    pub height: u32,
+    pub actions: Vec<TriggerAction>,
+    // 最初に入ったときだけ反応する
+    #[serde(default)]
+    pub once: bool,
+}
+
+// ボス戦の舞台（StartBossのトリガーで始まる）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BossArenaDefinition {
+    // ボスの出現位置（ボスの下半分が重なるタイル）
+    pub boss: TilePosition,
+    // ボス戦中にデスしたときのリスポーン位置
+    pub respawn: TilePosition,
+    // ボス戦開始時に出現する壁
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<TilePosition>,
+    #[serde(default = "default_boss_wall_image")]
+    pub wall_image: String,
+    // ボス戦中にカメラが写す範囲（省略するとステージ全体）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<TileArea>,
+    // 隕石などが降ってくる範囲。上端の行から降ってくる（省略するとカメラの範囲）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hazard: Option<TileArea>,
+}
+
+// マップの前後に描く飾りのレイヤー（当たり判定はない）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LayerDefinition {
+    // 描く順番（マップの背景は-1、壁は0、プレイヤーは2、画面の表示は3）
+    // 2と3の間にするとプレイヤーより手前に描く
+    pub z: f32,
+    // カメラに対して動く割合（1でマップと同じ、0で画面に固定、1より大きいと速く動く）
+    #[serde(default = "default_layer_scroll")]
+    pub scroll: f32,
+    // 縦方向の割合（省略するとscrollと同じ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll_y: Option<f32>,
+    // 凡例の文字で書いたタイル（空白には何も置かない）。一番下の行をマップの一番下の行に合わせる
+    pub map: Vec<String>,
+}
+
+fn default_layer_scroll() -> f32 {
+    1.
+}
+
+pub(crate) fn default_boss_wall_image() -> String {
+    "images/map/map2_3.png".to_string()
+}
+
+fn default_trigger_size() -> u32 {
+    1
+}
+
+pub(crate) fn default_platform_width() -> u32 {
+    1
+}
+
+pub(crate) fn default_platform_speed() -> f32 {
+    1.
+}
+
+pub(crate) fn default_crumble_time() -> f32 {
+    0.5
+}
+
+pub(crate) fn default_respawn_time() -> f32 {
+    3.
+}
+
+// ステージ定義ファイルの書式そのまま（Tiledからの変換もこの形にしてから検査する）
+#[derive(Deserialize, Serialize)]
+pub(crate) struct StageFile {
+    #[serde(serialize_with = "ordered_legend")]
+    pub(crate) legend: HashMap<char, TileDefinition>,
+    pub(crate) player_start: TilePosition,
+    pub(crate) enemies: Vec<EnemyPosition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) goal: Option<TilePosition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) checkpoints: Vec<TilePosition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) extra_lives: Vec<TilePosition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) ability_items: Vec<AbilityItemDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) triggers: Vec<TriggerDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) boss_arena: Option<BossArenaDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) moving_platforms: Vec<MovingPlatformDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) crumbling_platforms: Vec<CrumblingPlatformDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) layers: Vec<LayerDefinition>,
+    pub(crate) map: Vec<String>,
+}
+
+// 書き出すたびに順番が変わらないように文字順に並べる
+fn ordered_legend<S: serde::Serializer>(
+    legend: &HashMap<char, TileDefinition>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    legend
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+impl StageFile {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, StageLoadError> {
+        // 省略できる項目をSome(...)で囲まずに書けるようにする
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_bytes(bytes)
+            .map_err(StageLoadError::Parse)
+    }
+}
+
+#[derive(Debug, Clone, TypeUuid, TypePath)]
+#[uuid = "9a92c32d-4f7e-4d4c-af65-e6bb82843497"]
+pub struct StageData {
+    pub legend: HashMap<char, TileDefinition>,
+    pub player_start: TilePosition,
+    pub enemies: Vec<EnemyPosition>,
+    // ゴールがなければ右端に着いたらクリア
+    pub goal: Option<TilePosition>,
+    // 触れるとデス時のリスポーン位置になる
+    pub checkpoints: Vec<TilePosition>,
+    // 取ると残機が1増える（デスしても復活しない）
+    pub extra_lives: Vec<TilePosition>,
+    // 能力がもう使えるなら出さない
+    pub ability_items: Vec<AbilityItemDefinition>,
+    pub triggers: Vec<TriggerDefinition>,
+    pub boss_arena: Option<BossArenaDefinition>,
+    pub moving_platforms: Vec<MovingPlatformDefinition>,
+    pub crumbling_platforms: Vec<CrumblingPlatformDefinition>,
+    pub layers: Vec<LayerDefinition>,
+    // 上の行から順に並んでいる
+    pub map: Vec<Vec<char>>,
+}
+
+#[derive(Debug)]
+pub enum StageLoadError {
+    Parse(ron::error::SpannedError),
+    EmptyMap,
+    RowWidthMismatch {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownTile {
+        row: usize,
+        column: usize,
+        tile: char,
+    },
+    OutOfMap {
+        what: String,
+        x: u32,
+        y: u32,
+    },
+    EmptyPath(usize),
+    InvalidPatrol(usize),
+    MissingBossArena(usize),
+    UnknownLayerTile {
+        layer: usize,
+        row: usize,
+        column: usize,
+        tile: char,
+    },
+    InvalidAnimation(char),
+}
+
+impl fmt::Display for StageLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageLoadError::Parse(error) => write!(f, "invalid stage file: {}", error),
+            StageLoadError::EmptyMap => write!(f, "map has no rows"),
+            StageLoadError::RowWidthMismatch {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {}: expected {} tiles but found {}",
+                row, expected, found
+            ),
+            StageLoadError::UnknownTile { row, column, tile } => write!(
+                f,
+                "row {}, column {}: tile '{}' is not defined in legend",
+                row, column, tile
+            ),
+            StageLoadError::OutOfMap { what, x, y } => {
+                write!(f, "{} at (x: {}, y: {}) is outside of the map", what, x, y)
+            }
+            StageLoadError::EmptyPath(index) => {
+                write!(f, "moving platform #{} has no path", index)
+            }
+            StageLoadError::InvalidPatrol(index) => {
+                write!(
+                    f,
+                    "enemy #{} has a patrol area that does not contain its position",
+                    index
+                )
+            }
+            StageLoadError::MissingBossArena(index) => {
+                write!(
+                    f,
+                    "trigger #{} starts the boss but there is no boss_arena",
+                    index
+                )
+            }
+            StageLoadError::UnknownLayerTile {
+                layer,
+                row,
+                column,
+                tile,
+            } => write!(
+                f,
+                "layer #{}, row {}, column {}: tile '{}' is not defined in legend",
+                layer, row, column, tile
+            ),
+            StageLoadError::InvalidAnimation(tile) => write!(
+                f,
+                "tile '{}' needs at least 1 animation frame and a positive frame_time",
+                tile
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StageLoadError {}
+
+impl StageData {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StageLoadError> {
+        StageData::from_file(StageFile::from_bytes(bytes)?)
+    }
+
+    pub(crate) fn from_file(file: StageFile) -> Result<Self, StageLoadError> {
+        if let Some(error) = map_errors(&file.legend, &file.map).into_iter().next() {
+            return Err(error);
+        }
+        // 0秒以下やNaNではアニメーションのタイマーを作れない
+        if let Some(tile) = file
+            .legend
+            .iter()
+            .filter(|(_, definition)| {
+                definition
+                    .animation
+                    .is_some_and(|animation| !animation.is_valid())
+            })
+            .map(|(tile, _)| *tile)
+            .min()
+        {
+            return Err(StageLoadError::InvalidAnimation(tile));
+        }
+        let map = file
+            .map
+            .iter()
+            .map(|row| row.chars().collect::<Vec<char>>())
+            .collect::<Vec<Vec<char>>>();
+
+        let stage = StageData {
+            legend: file.legend,
+            player_start: file.player_start,
+            enemies: file.enemies,
+            goal: file.goal,
+            checkpoints: file.checkpoints,
+            extra_lives: file.extra_lives,
+            ability_items: file.ability_items,
+            triggers: file.triggers,
+            boss_arena: file.boss_arena,
+            moving_platforms: file.moving_platforms,
+            crumbling_platforms: file.crumbling_platforms,
+            layers: file.layers,
+            map,
+        };
+
+        let mut positions = vec![("player start".to_string(), stage.player_start)];
+        positions.extend(stage.goal.map(|goal| ("goal".to_string(), goal)));
+        for (i, enemy) in stage.enemies.iter().enumerate() {
+            positions.push((
+                format!("enemy #{}", i),
+                TilePosition {
+                    x: enemy.x,
+                    y: enemy.y,
+                },
+            ));
+            if let Some(patrol) = enemy.patrol {
+                let top = patrol.top.unwrap_or(enemy.y);
+                let bottom = patrol.bottom.unwrap_or(enemy.y);
+                if !(patrol.left..=patrol.right).contains(&enemy.x)
+                    || !(top..=bottom).contains(&enemy.y)
+                {
+                    return Err(StageLoadError::InvalidPatrol(i));
+                }
+                positions.push((
+                    format!("enemy #{} patrol area", i),
+                    TilePosition {
+                        x: patrol.right,
+                        y: bottom,
+                    },
+                ));
+            }
+        }
+        for (i, checkpoint) in stage.checkpoints.iter().enumerate() {
+            positions.push((format!("checkpoint #{}", i), *checkpoint));
+        }
+        for (i, extra_life) in stage.extra_lives.iter().enumerate() {
+            positions.push((format!("extra life #{}", i), *extra_life));
+        }
+        for (i, item) in stage.ability_items.iter().enumerate() {
+            positions.push((
+                format!("ability item #{}", i),
+                TilePosition {
+                    x: item.x,
+                    y: item.y,
+                },
+            ));
+        }
+        for (i, trigger) in stage.triggers.iter().enumerate() {
+            // 右下のタイルまでマップに収まっていること
+            positions.push((
+                format!("trigger #{}", i),
+                TilePosition {
+                    x: trigger.x + trigger.width.max(1) - 1,
+                    y: trigger.y + trigger.height.max(1) - 1,
+                },
+            ));
+            for action in trigger.actions.iter() {
+                match action {
+                    TriggerAction::LockCamera { x, y } => positions.push((
+                        format!("trigger #{} camera lock", i),
+                        TilePosition { x: *x, y: *y },
+                    )),
+                    TriggerAction::SpawnEnemies(enemies) => {
+                        for (j, enemy) in enemies.iter().enumerate() {
+                            positions.push((
+                                format!("trigger #{} enemy #{}", i, j),
+                                TilePosition {
+                                    x: enemy.x,
+                                    y: enemy.y,
+                                },
+                            ));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(arena) = &stage.boss_arena {
+            positions.push(("boss".to_string(), arena.boss));
+            positions.push(("boss respawn".to_string(), arena.respawn));
+            for (i, wall) in arena.walls.iter().enumerate() {
+                positions.push((format!("boss wall #{}", i), *wall));
+            }
+            for (what, area) in [
+                ("boss camera area", arena.camera),
+                ("boss hazard area", arena.hazard),
+            ] {
+                if let Some(area) = area {
+                    positions.push((what.to_string(), area.last()));
+                }
+            }
+        } else if let Some(i) = stage.triggers.iter().position(|trigger| {
+            trigger
+                .actions
+                .iter()
+                .any(|action| matches!(action, TriggerAction::StartBoss))
+        }) {
+            return Err(StageLoadError::MissingBossArena(i));
+        }
+        for (i, platform) in stage.moving_platforms.iter().enumerate() {
+            if platform.path.is_empty() {
+                return Err(StageLoadError::EmptyPath(i));
+            }
+            for (j, position) in platform.path.iter().enumerate() {
+                // 右端のタイルまでマップに収まっていること
+                positions.push((
+                    format!("moving platform #{} waypoint #{}", i, j),
+                    TilePosition {
+                        x: position.x + platform.width.max(1) - 1,
+                        y: position.y,
+                    },
+                ));
+            }
+        }
+        for (i, platform) in stage.crumbling_platforms.iter().enumerate() {
+            positions.push((
+                format!("crumbling platform #{}", i),
+                TilePosition {
+                    x: platform.x + platform.width.max(1) - 1,
+                    y: platform.y,
+                },
+            ));
+        }
+        for (i, layer) in stage.layers.iter().enumerate() {
+            for (row, tiles) in layer.map.iter().enumerate() {
+                for (column, tile) in tiles.chars().enumerate() {
+                    if tile != ' ' && !stage.legend.contains_key(&tile) {
+                        return Err(StageLoadError::UnknownLayerTile {
+                            layer: i,
+                            row,
+                            column,
+                            tile,
+                        });
+                    }
+                }
+            }
+        }
+        for (what, position) in positions {
+            if !stage.contains(position.x, position.y) {
+                return Err(StageLoadError::OutOfMap {
+                    what,
+                    x: position.x,
+                    y: position.y,
+                });
+            }
+        }
+
+        Ok(stage)
+    }
+
+    // ステージ定義ファイルの書式で書き出す（コメントは残らない）
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        let file = StageFile {
+            legend: self.legend.clone(),
+            player_start: self.player_start,
+            enemies: self.enemies.clone(),
+            goal: self.goal,
+            checkpoints: self.checkpoints.clone(),
+            extra_lives: self.extra_lives.clone(),
+            ability_items: self.ability_items.clone(),
+            triggers: self.triggers.clone(),
+            boss_arena: self.boss_arena.clone(),
+            moving_platforms: self.moving_platforms.clone(),
+            crumbling_platforms: self.crumbling_platforms.clone(),
+            layers: self.layers.clone(),
+            map: self.map.iter().map(|row| row.iter().collect()).collect(),
+        };
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default().depth_limit(2))
+    }
+
+    pub fn width(&self) -> usize {
+        self.map[0].len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.map.len()
+    }
+
+    // カメラが写せる範囲（右端の1列は壁の外側なので写さない）
+    pub fn camera_area(&self) -> TileArea {
+        TileArea {
+            x: 0,
+            y: 0,
+            width: self.width() as u32 - 1,
+            height: self.height() as u32,
+        }
+    }
+
+    fn contains(&self, x: u32, y: u32) -> bool {
+        (x as usize) < self.width() && (y as usize) < self.height()
+    }
+
+    // 上から数えた行を下から数えた行に変換する
+    pub fn row_from_bottom(&self, y: u32) -> usize {
+        self.height() - 1 - y as usize
+    }
+
+    // レイヤーの(列, 下から数えた行, タイル定義)を返す（空白は除く）
+    pub fn layer_tiles<'a>(
+        &'a self,
+        layer: &'a LayerDefinition,
+    ) -> impl Iterator<Item = (usize, usize, &'a TileDefinition)> {
+        let height = layer.map.len();
+        layer.map.iter().enumerate().flat_map(move |(row, tiles)| {
+            tiles
+                .chars()
+                .enumerate()
+                .filter(|(_, tile)| *tile != ' ')
+                .map(move |(column, tile)| (column, height - 1 - row, &self.legend[&tile]))
+        })
+    }
+
+    // (列, 下から数えた行, タイル定義)を返す
+    pub fn tiles(&self) -> impl Iterator<Item = (usize, usize, &TileDefinition)> {
+        let height = self.height();
+        self.map.iter().enumerate().flat_map(move |(row, tiles)| {
+            tiles
+                .iter()
+                .enumerate()
+                .map(move |(column, tile)| (column, height - 1 - row, &self.legend[tile]))
+        })
+    }
+}
+
+// マップの行の長さと凡例にないタイルをすべて調べる
+pub(crate) fn map_errors(
+    legend: &HashMap<char, TileDefinition>,
+    map: &[String],
+) -> Vec<StageLoadError> {
+    let Some(width) = map.first().map(|row| row.chars().count()) else {
+        return vec![StageLoadError::EmptyMap];
+    };
+
+    let mut errors = Vec::new();
+    for (row, tiles) in map.iter().enumerate() {
+        let found = tiles.chars().count();
+        if found != width {
+            errors.push(StageLoadError::RowWidthMismatch {
+                row,
+                expected: width,
+                found,
+            });
+        }
+        for (column, tile) in tiles.chars().enumerate() {
+            if !legend.contains_key(&tile) {
+                errors.push(StageLoadError::UnknownTile { row, column, tile });
+            }
+        }
+    }
+    errors
+}
+
+#[derive(Default)]
+pub struct StageLoader;
+
+impl AssetLoader for StageLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            // どのファイルのエラーか分かるようにパスを付けておく
+            let stage = StageData::from_bytes(bytes).map_err(|error| {
+                Error::msg(format!("{}: {}", load_context.path().display(), error))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(stage));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stage.ron"]
+    }
+}
+
+// テスト用のステージ定義ファイル（'.'と','は背景、'#'は壁）
+// objectsにはplayer_startなどマップ以外の項目を書く
+#[cfg(test)]
+pub(crate) fn test_stage_source(objects: &str, map: &[&str]) -> String {
+    let map = map
+        .iter()
+        .map(|row| format!("        \"{}\",", row))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"(
+    legend: {{
+        '.': (kind: Background, image: "images/map/map_1.png"),
+        ',': (kind: Background, image: "images/map/map_2.png"),
+        '#': (kind: Wall, image: "images/map/map_3.png"),
+    }},
+    {}
+    map: [
+{}
+    ],
+)"#,
+        objects, map
+    )
+}
+
+#[cfg(test)]
+pub(crate) fn test_stage(objects: &str, map: &[&str]) -> StageData {
+    StageData::from_bytes(test_stage_source(objects, map).as_bytes()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 書き出したものを読み直して、もう一度書き出しても同じになるか
+    fn round_trip(stage: &StageData) -> StageData {
+        let ron = stage.to_ron().unwrap();
+        let reloaded =
+            StageData::from_file(StageFile::from_bytes(ron.as_bytes()).unwrap()).unwrap();
+        assert_eq!(reloaded.to_ron().unwrap(), ron);
+        reloaded
+    }
+
+    #[test]
+    fn to_ron_round_trips_optional_fields() {
+        // 省略できる項目はSome(...)で囲まずに書く
+        let source = r#"(
+    legend: {
+        '.': (kind: Background, image: "images/map/map_1.png"),
+        '~': (kind: Background, image: "images/map/water.png", animation: (frames: 4, frame_time: 0.5)),
+        'C': (kind: Wall, image: "images/map/map_3.png", autotile: true),
+    },
+    player_start: (x: 1, y: 2),
+    enemies: [
+        (x: 3, y: 2, kind: Lizard, facing: Left, walk_step: 2.5, move_lifetime: 45,
+            patrol: (left: 1, right: 4, top: 1)),
+    ],
+    goal: (x: 4, y: 2),
+    extra_lives: [(x: 1, y: 1)],
+    ability_items: [(x: 2, y: 2, ability: DoubleJump)],
+    boss_arena: (boss: (x: 2, y: 1), respawn: (x: 1, y: 1), camera: (x: 0, y: 0, width: 5, height: 4)),
+    layers: [(z: 2.5, scroll: 0.5, scroll_y: 0., map: ["  ~  "])],
+    map: [
+        "C.....",
+        "C.....",
+        "C..~..",
+        "CCCCCC",
+    ],
+)"#;
+        let stage = round_trip(&StageData::from_bytes(source.as_bytes()).unwrap());
+
+        assert_eq!(stage.goal.map(|goal| (goal.x, goal.y)), Some((4, 2)));
+        assert_eq!(
+            stage
+                .extra_lives
+                .iter()
+                .map(|extra_life| (extra_life.x, extra_life.y))
+                .collect::<Vec<_>>(),
+            vec![(1, 1)]
+        );
+        let item = stage.ability_items[0];
+        assert_eq!((item.x, item.y, item.ability), (2, 2, Ability::DoubleJump));
+        let enemy = stage.enemies[0];
+        assert_eq!(enemy.facing, Facing::Left);
+        assert_eq!(enemy.walk_step, Some(2.5));
+        assert_eq!(enemy.move_lifetime, Some(45));
+        let patrol = enemy.patrol.unwrap();
+        assert_eq!((patrol.top, patrol.bottom), (Some(1), None));
+        let arena = stage.boss_arena.unwrap();
+        assert_eq!(arena.camera.map(|camera| camera.width), Some(5));
+        assert!(arena.hazard.is_none());
+        assert_eq!(stage.layers[0].scroll_y, Some(0.));
+        assert_eq!(
+            stage.legend[&'~'].animation,
+            Some(TileAnimation {
+                frames: 4,
+                frame_time: 0.5,
+            })
+        );
+        assert!(stage.legend[&'C'].autotile);
+    }
+
+    fn load(objects: &str, map: &[&str]) -> Result<StageData, StageLoadError> {
+        StageData::from_bytes(test_stage_source(objects, map).as_bytes())
+    }
+
+    const OBJECTS: &str = "player_start: (x: 1, y: 1), enemies: [],";
+
+    #[test]
+    fn rejects_empty_map() {
+        assert!(matches!(load(OBJECTS, &[]), Err(StageLoadError::EmptyMap)));
+    }
+
+    #[test]
+    fn rejects_rows_of_different_width() {
+        assert!(matches!(
+            load(OBJECTS, &["#...", "#..", "####"]),
+            Err(StageLoadError::RowWidthMismatch {
+                row: 1,
+                expected: 4,
+                found: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_tile() {
+        assert!(matches!(
+            load(OBJECTS, &["#...", "#.x.", "####"]),
+            Err(StageLoadError::UnknownTile {
+                row: 1,
+                column: 2,
+                tile: 'x',
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_positions_outside_of_map() {
+        let error = load(
+            "player_start: (x: 1, y: 1), enemies: [(x: 4, y: 1, kind: Slime)],",
+            &["#...", "#...", "####"],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            &error,
+            StageLoadError::OutOfMap { what, x: 4, y: 1 } if what == "enemy #0"
+        ));
+
+        assert!(matches!(
+            load(
+                "player_start: (x: 1, y: 3), enemies: [],",
+                &["#...", "#...", "####"],
+            ),
+            Err(StageLoadError::OutOfMap { x: 1, y: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_animation() {
+        for animation in [
+            "(frames: 2, frame_time: -0.5)",
+            "(frames: 2, frame_time: 0.)",
+            "(frames: 0)",
+        ] {
+            let source = format!(
+                r#"(
+    legend: {{
+        '.': (kind: Background, image: "images/map/map_1.png"),
+        '~': (kind: Background, image: "images/map/water.png", animation: {}),
+    }},
+    player_start: (x: 0, y: 0),
+    enemies: [],
+    map: [".~"],
+)"#,
+                animation
+            );
+            assert!(matches!(
+                StageData::from_bytes(source.as_bytes()),
+                Err(StageLoadError::InvalidAnimation('~'))
+            ));
+        }
+    }
+
+    #[test]
+    fn shipped_stages_round_trip() {
+        for source in [
+            include_str!("../assets/stages/stage1.stage.ron"),
+            include_str!("../assets/stages/stage2.stage.ron"),
+        ] {
+            round_trip(&StageData::from_bytes(source.as_bytes()).unwrap());
+        }
+    }
+}
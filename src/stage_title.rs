@@ -4,8 +4,9 @@ use try_rust_bevy::utils::*;
 pub mod stage_title_scene {
     use bevy::prelude::*;
     use try_rust_bevy::consts::BossState;
+    use try_rust_bevy::stage::CurrentStage;
 
-    use super::{despawn_screen, GameState, StageState};
+    use super::{despawn_screen, GameState};
 
     pub struct StageTitlePlugin;
 
@@ -27,15 +28,12 @@ pub mod stage_title_scene {
     fn stage_title_setup(
         mut commands: Commands,
         asset_server: Res<AssetServer>,
-        stage_state: Res<State<StageState>>,
+        current_stage: Res<CurrentStage>,
         mut boss_state: ResMut<NextState<BossState>>,
     ) {
         commands.spawn((
             SpriteBundle {
-                texture: asset_server.load(match stage_state.get() {
-                    StageState::Stage1 => "images/scene/scene_3.png",
-                    StageState::Stage2 | StageState::Boss => "images/scene/scene_4.png",
-                }),
+                texture: asset_server.load(&current_stage.entry.title_card),
                 sprite: Sprite::default(),
                 ..default()
             },
@@ -0,0 +1,1432 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use crate::consts::ASSETS_DIR;
+use crate::stage::{
+    default_boss_wall_image, default_crumble_time, default_platform_speed, default_platform_width,
+    default_respawn_time, Ability, AbilityItemDefinition, BossArenaDefinition,
+    CrumblingPlatformDefinition, EnemyKind, EnemyPosition, Facing, MovingPlatformDefinition,
+    PatrolBounds, StageData, StageFile, StageLoadError, TileArea, TileDefinition, TileKind,
+    TilePosition, TriggerAction, TriggerDefinition,
+};
+
+// gidの上位ビットは反転・回転フラグなので取り除く
+const GID_MASK: u32 = 0x0FFF_FFFF;
+
+// 変換後のマップで使う記号（足りなければ記号以外の文字も使う）
+const LEGEND_SYMBOLS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+// Tiledで作ったマップ(.tmx/.tmj)をステージとして読み込む
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let path = load_context.path().to_path_buf();
+            let stage = stage_from_tiled(bytes, &path)
+                .map_err(|error| Error::msg(format!("{}: {}", path.display(), error)))?;
+            load_context.set_default_asset(LoadedAsset::new(stage));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+// 拡張子でTiledのマップかどうかを判断する
+pub fn is_tiled_map(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("tmx") | Some("tmj")
+    )
+}
+
+// Tiledのマップの画像のパスはassetsから数えるので、assets/で始まるパスはassetsからのパスにする
+pub fn asset_path(path: &Path) -> &Path {
+    path.strip_prefix(ASSETS_DIR).unwrap_or(path)
+}
+
+// ツール用。ファイルを読んで、ステージ定義ファイルかTiledのマップとして読み込む
+pub fn load_stage_at(path: &Path) -> Result<StageData, TiledLoadError> {
+    let bytes = std::fs::read(path).map_err(TiledLoadError::Io)?;
+    if is_tiled_map(path) {
+        stage_from_tiled(&bytes, asset_path(path))
+    } else {
+        StageData::from_bytes(&bytes).map_err(TiledLoadError::Stage)
+    }
+}
+
+// pathはassetsからのパス（画像のパスを解決するのに使う）
+pub fn stage_from_tiled(bytes: &[u8], path: &Path) -> Result<StageData, TiledLoadError> {
+    let map = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmj") => TiledMap::from_json(bytes)?,
+        _ => TiledMap::from_xml(bytes)?,
+    };
+    map.into_stage(path)
+}
+
+#[derive(Debug)]
+pub enum TiledLoadError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+    UnknownTile {
+        layer: String,
+        row: usize,
+        column: usize,
+        gid: u32,
+    },
+    MissingTile {
+        row: usize,
+        column: usize,
+    },
+    Stage(StageLoadError),
+}
+
+impl fmt::Display for TiledLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiledLoadError::Io(error) => write!(f, "{}", error),
+            TiledLoadError::Xml(error) => write!(f, "invalid tmx file: {}", error),
+            TiledLoadError::Json(error) => write!(f, "invalid tmj file: {}", error),
+            TiledLoadError::Invalid(message) => write!(f, "{}", message),
+            TiledLoadError::UnknownTile {
+                layer,
+                row,
+                column,
+                gid,
+            } => write!(
+                f,
+                "layer '{}', row {}, column {}: tile gid {} has no image in any embedded tileset",
+                layer, row, column, gid
+            ),
+            TiledLoadError::MissingTile { row, column } => write!(
+                f,
+                "row {}, column {}: no tile in any layer and the map has no 'background' property",
+                row, column
+            ),
+            TiledLoadError::Stage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TiledLoadError {}
+
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    properties: HashMap<String, String>,
+    tilesets: Vec<TiledTileset>,
+    layers: Vec<TiledLayer>,
+}
+
+struct TiledTileset {
+    first_gid: u32,
+    tiles: HashMap<u32, TiledTile>,
+}
+
+struct TiledTile {
+    image: Option<String>,
+    properties: HashMap<String, String>,
+}
+
+enum TiledLayer {
+    Tiles { name: String, data: Vec<u32> },
+    Objects { objects: Vec<TiledObject> },
+}
+
+struct TiledObject {
+    // Tiled 1.9以降はclass、それより前はtype。どちらも無ければname
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    gid: Option<u32>,
+    // ポリラインの頂点（x, yからの相対位置）
+    points: Vec<(f32, f32)>,
+    properties: HashMap<String, String>,
+}
+
+impl TiledMap {
+    fn from_xml(bytes: &[u8]) -> Result<Self, TiledLoadError> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| TiledLoadError::Invalid("tmx file is not UTF-8".to_string()))?;
+        let document = roxmltree::Document::parse(text).map_err(TiledLoadError::Xml)?;
+        let root = document.root_element();
+        if !root.has_tag_name("map") {
+            return Err(TiledLoadError::Invalid(
+                "root element is not <map>".to_string(),
+            ));
+        }
+        if root.attribute("infinite") == Some("1") {
+            return Err(TiledLoadError::Invalid(
+                "infinite maps are not supported".to_string(),
+            ));
+        }
+
+        let mut tilesets = Vec::new();
+        for node in root.children().filter(|node| node.has_tag_name("tileset")) {
+            tilesets.push(xml_tileset(node)?);
+        }
+        let mut layers = Vec::new();
+        xml_layers(root, &mut layers)?;
+
+        Ok(TiledMap {
+            width: xml_number(root, "width")?,
+            height: xml_number(root, "height")?,
+            tile_width: xml_number(root, "tilewidth")?,
+            tile_height: xml_number(root, "tileheight")?,
+            properties: xml_properties(root),
+            tilesets,
+            layers,
+        })
+    }
+
+    fn from_json(bytes: &[u8]) -> Result<Self, TiledLoadError> {
+        let map: JsonMap = serde_json::from_slice(bytes).map_err(TiledLoadError::Json)?;
+        if map.infinite {
+            return Err(TiledLoadError::Invalid(
+                "infinite maps are not supported".to_string(),
+            ));
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in map.tilesets {
+            if let Some(source) = tileset.source {
+                return Err(external_tileset_error(&source));
+            }
+            tilesets.push(TiledTileset {
+                first_gid: tileset.firstgid,
+                tiles: tileset
+                    .tiles
+                    .into_iter()
+                    .map(|tile| {
+                        (
+                            tile.id,
+                            TiledTile {
+                                image: tile.image,
+                                properties: json_properties(tile.properties),
+                            },
+                        )
+                    })
+                    .collect(),
+            });
+        }
+        let mut layers = Vec::new();
+        json_layers(map.layers, &mut layers)?;
+
+        Ok(TiledMap {
+            width: map.width,
+            height: map.height,
+            tile_width: map.tilewidth,
+            tile_height: map.tileheight,
+            properties: json_properties(map.properties),
+            tilesets,
+            layers,
+        })
+    }
+
+    fn tile_definition(
+        &self,
+        gid: u32,
+        base: &Path,
+    ) -> Result<Option<TileDefinition>, TiledLoadError> {
+        let Some(tileset) = self
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+        else {
+            return Ok(None);
+        };
+        let Some(tile) = tileset.tiles.get(&(gid - tileset.first_gid)) else {
+            return Ok(None);
+        };
+        // imageプロパティがあれば画像を差し替える
+        let Some(image) = tile.properties.get("image").or(tile.image.as_ref()) else {
+            return Ok(None);
+        };
+        let kind = match tile.properties.get("kind").map(|kind| kind.as_str()) {
+            None | Some("background") => TileKind::Background,
+            Some("wall") => TileKind::Wall,
+            Some("platform") => TileKind::Platform,
+            Some("spike") => TileKind::Spike,
+            Some("ladder") => TileKind::Ladder,
+            Some(kind) => {
+                return Err(TiledLoadError::Invalid(format!(
+                    "tile gid {} has unknown kind '{}'",
+                    gid, kind
+                )))
+            }
+        };
+        Ok(Some(TileDefinition {
+            kind,
+            image: resolve_path(base, image),
+            autotile: false,
+            animation: None,
+        }))
+    }
+
+    fn into_stage(self, path: &Path) -> Result<StageData, TiledLoadError> {
+        // 画像のパスはマップファイルからの相対パスになっている
+        let base = path.parent().unwrap_or(Path::new(""));
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        // 各マスのタイルを決める。壁、足場などの仕掛け、背景の順にどのレイヤーにあっても優先し、
+        // 同じ優先度なら上のレイヤーを優先する
+        let mut cells: Vec<Option<TileDefinition>> = vec![None; width * height];
+        for layer in self.layers.iter() {
+            let TiledLayer::Tiles { name, data } = layer else {
+                continue;
+            };
+            if data.len() != width * height {
+                return Err(TiledLoadError::Invalid(format!(
+                    "layer '{}' has {} tiles but the map is {}x{}",
+                    name,
+                    data.len(),
+                    width,
+                    height
+                )));
+            }
+            for (i, raw_gid) in data.iter().enumerate() {
+                let gid = raw_gid & GID_MASK;
+                if gid == 0 {
+                    continue;
+                }
+                let tile = self
+                    .tile_definition(gid, base)?
+                    .ok_or(TiledLoadError::UnknownTile {
+                        layer: name.clone(),
+                        row: i / width,
+                        column: i % width,
+                        gid,
+                    })?;
+                let keep =
+                    matches!(&cells[i], Some(cell) if priority(cell.kind) > priority(tile.kind));
+                if !keep {
+                    cells[i] = Some(tile);
+                }
+            }
+        }
+
+        // どのレイヤーにもタイルが無いマスはbackgroundプロパティの画像で埋める
+        let background = self
+            .properties
+            .get("background")
+            .map(|image| TileDefinition {
+                kind: TileKind::Background,
+                image: resolve_path(base, image),
+                autotile: false,
+                animation: None,
+            });
+
+        let mut symbols = LEGEND_SYMBOLS
+            .chars()
+            .chain((0x100..).filter_map(char::from_u32));
+        let mut symbol_by_tile: HashMap<(TileKind, String), char> = HashMap::new();
+        let mut legend = HashMap::new();
+        let mut map = Vec::new();
+        for row in 0..height {
+            let mut line = String::new();
+            for column in 0..width {
+                let tile = cells[row * width + column]
+                    .clone()
+                    .or(background.clone())
+                    .ok_or(TiledLoadError::MissingTile { row, column })?;
+                let symbol = *symbol_by_tile
+                    .entry((tile.kind, tile.image.clone()))
+                    .or_insert_with(|| {
+                        let symbol = symbols.next().unwrap();
+                        legend.insert(symbol, tile);
+                        symbol
+                    });
+                line.push(symbol);
+            }
+            map.push(line);
+        }
+
+        let mut player_start = None;
+        let mut enemies = Vec::new();
+        let mut goal = None;
+        let mut checkpoints = Vec::new();
+        let mut extra_lives = Vec::new();
+        let mut ability_items = Vec::new();
+        let mut triggers = Vec::new();
+        // spawn_groupを指定した敵は同じspawn_groupのトリガーに入ったときに出現する
+        let mut spawn_groups: HashMap<String, Vec<EnemyPosition>> = HashMap::new();
+        let mut boss = None;
+        let mut boss_wall_image = None;
+        let mut boss_respawn = None;
+        let mut boss_walls = Vec::new();
+        let mut boss_camera = None;
+        let mut boss_hazard = None;
+        let mut moving_platforms = Vec::new();
+        let mut crumbling_platforms = Vec::new();
+        for layer in self.layers.iter() {
+            let TiledLayer::Objects { objects } = layer else {
+                continue;
+            };
+            for object in objects.iter() {
+                match object.kind.as_str() {
+                    "player_start" => player_start = Some(self.object_position(object)?),
+                    "enemy" => {
+                        let enemy = self.object_enemy(object)?;
+                        match object.properties.get("spawn_group") {
+                            Some(group) => {
+                                spawn_groups.entry(group.clone()).or_default().push(enemy)
+                            }
+                            None => enemies.push(enemy),
+                        }
+                    }
+                    "trigger" => triggers.push((self.object_trigger(object, base)?, object)),
+                    "goal" => goal = Some(self.object_position(object)?),
+                    "checkpoint" => checkpoints.push(self.object_position(object)?),
+                    "extra_life" => extra_lives.push(self.object_position(object)?),
+                    "ability" => ability_items.push(self.object_ability(object)?),
+                    "boss_spawn" => {
+                        boss = Some(self.object_position(object)?);
+                        // ボス戦開始時に出現する壁の画像（省略すると元の画像）
+                        boss_wall_image = object
+                            .properties
+                            .get("wall_image")
+                            .map(|image| resolve_path(base, image));
+                    }
+                    "boss_respawn" => boss_respawn = Some(self.object_position(object)?),
+                    "boss_wall" => boss_walls.extend(self.object_area(object)?),
+                    "boss_camera" => boss_camera = Some(self.object_tile_area(object)?),
+                    "boss_hazard" => boss_hazard = Some(self.object_tile_area(object)?),
+                    "moving_platform" => {
+                        moving_platforms.push(self.object_moving_platform(object, base)?)
+                    }
+                    "crumbling_platform" => {
+                        crumbling_platforms.push(self.object_crumbling_platform(object, base)?)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let triggers = triggers
+            .into_iter()
+            .map(|(mut trigger, object)| {
+                if let Some(group) = object.properties.get("spawn_group") {
+                    let Some(enemies) = spawn_groups.remove(group) else {
+                        return Err(invalid_property(object, "spawn_group", group));
+                    };
+                    trigger.actions.push(TriggerAction::SpawnEnemies(enemies));
+                }
+                Ok(trigger)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(group) = spawn_groups.keys().next() {
+            return Err(TiledLoadError::Invalid(format!(
+                "no trigger object has spawn_group '{}'",
+                group
+            )));
+        }
+
+        // ボス戦の舞台はboss_spawnがあるときだけ
+        let boss_arena = match boss {
+            Some(boss) => Some(BossArenaDefinition {
+                boss,
+                respawn: boss_respawn.ok_or_else(|| {
+                    TiledLoadError::Invalid("boss_spawn needs a boss_respawn object".to_string())
+                })?,
+                walls: boss_walls,
+                wall_image: boss_wall_image.unwrap_or_else(default_boss_wall_image),
+                camera: boss_camera,
+                hazard: boss_hazard,
+            }),
+            None if boss_respawn.is_none()
+                && boss_walls.is_empty()
+                && boss_camera.is_none()
+                && boss_hazard.is_none() =>
+            {
+                None
+            }
+            None => {
+                return Err(TiledLoadError::Invalid(
+                    "boss arena objects need a boss_spawn object".to_string(),
+                ))
+            }
+        };
+
+        let Some(player_start) = player_start else {
+            return Err(TiledLoadError::Invalid(
+                "no player_start object in any object layer".to_string(),
+            ));
+        };
+
+        StageData::from_file(StageFile {
+            legend,
+            player_start,
+            enemies,
+            goal,
+            checkpoints,
+            extra_lives,
+            ability_items,
+            triggers,
+            boss_arena,
+            moving_platforms,
+            crumbling_platforms,
+            layers: Vec::new(),
+            map,
+        })
+        .map_err(TiledLoadError::Stage)
+    }
+
+    // オブジェクトの上端（タイルオブジェクトは座標が下端になっている）
+    fn object_top(&self, object: &TiledObject) -> f32 {
+        if object.gid.is_some() {
+            object.y - object.height
+        } else {
+            object.y
+        }
+    }
+
+    // オブジェクトの中心があるタイル
+    fn object_position(&self, object: &TiledObject) -> Result<TilePosition, TiledLoadError> {
+        Ok(TilePosition {
+            x: tile_index(object, object.x + object.width / 2., self.tile_width)?,
+            y: tile_index(
+                object,
+                self.object_top(object) + object.height / 2.,
+                self.tile_height,
+            )?,
+        })
+    }
+
+    // 能力はオブジェクトのabilityプロパティで指定する
+    fn object_ability(
+        &self,
+        object: &TiledObject,
+    ) -> Result<AbilityItemDefinition, TiledLoadError> {
+        let position = self.object_position(object)?;
+        let ability = match object.properties.get("ability").map(|value| value.as_str()) {
+            Some("double_jump") => Ability::DoubleJump,
+            Some("dash") => Ability::Dash,
+            Some("wall_jump") => Ability::WallJump,
+            Some(ability) => return Err(invalid_property(object, "ability", ability)),
+            None => {
+                return Err(TiledLoadError::Invalid(format!(
+                    "ability object at ({}, {}) has no ability property",
+                    object.x, object.y
+                )))
+            }
+        };
+        Ok(AbilityItemDefinition {
+            x: position.x,
+            y: position.y,
+            ability,
+        })
+    }
+
+    // 敵の種類などはオブジェクトのプロパティで指定する
+    fn object_enemy(&self, object: &TiledObject) -> Result<EnemyPosition, TiledLoadError> {
+        let position = self.object_position(object)?;
+        let property = |name: &str| object.properties.get(name).map(|value| value.as_str());
+
+        let kind = match property("kind") {
+            Some("slime") => EnemyKind::Slime,
+            Some("lizard") => EnemyKind::Lizard,
+            Some("wizard") => EnemyKind::Wizard,
+            Some("red_demon") => EnemyKind::RedDemon,
+            Some(kind) => return Err(invalid_property(object, "kind", kind)),
+            None => {
+                return Err(TiledLoadError::Invalid(format!(
+                    "enemy object at ({}, {}) has no kind property",
+                    object.x, object.y
+                )))
+            }
+        };
+        let facing = match property("facing") {
+            None | Some("right") => Facing::Right,
+            Some("left") => Facing::Left,
+            Some(facing) => return Err(invalid_property(object, "facing", facing)),
+        };
+        let patrol = match (
+            object_number(object, "patrol_left")?,
+            object_number(object, "patrol_right")?,
+        ) {
+            (Some(left), Some(right)) => Some(PatrolBounds {
+                left,
+                right,
+                top: object_number(object, "patrol_top")?,
+                bottom: object_number(object, "patrol_bottom")?,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(TiledLoadError::Invalid(format!(
+                    "enemy object at ({}, {}) needs both patrol_left and patrol_right",
+                    object.x, object.y
+                )))
+            }
+        };
+
+        Ok(EnemyPosition {
+            x: position.x,
+            y: position.y,
+            kind,
+            facing,
+            walk_step: object_number(object, "walk_step")?,
+            move_lifetime: object_number(object, "move_lifetime")?,
+            patrol,
+        })
+    }
+
+    // トリガーの動作はオブジェクトのプロパティで指定する（敵の出現はspawn_groupで指定）
+    fn object_trigger(
+        &self,
+        object: &TiledObject,
+        base: &Path,
+    ) -> Result<TriggerDefinition, TiledLoadError> {
+        let area = self.object_tile_area(object)?;
+
+        let mut actions = Vec::new();
+        if object_number(object, "clear_stage")?.unwrap_or(false) {
+            actions.push(TriggerAction::ClearStage);
+        }
+        if object_number(object, "start_boss")?.unwrap_or(false) {
+            actions.push(TriggerAction::StartBoss);
+        }
+        match (
+            object_number(object, "lock_camera_x")?,
+            object_number(object, "lock_camera_y")?,
+        ) {
+            (Some(x), Some(y)) => actions.push(TriggerAction::LockCamera { x, y }),
+            (None, None) => {}
+            _ => {
+                return Err(TiledLoadError::Invalid(format!(
+                    "trigger object at ({}, {}) needs both lock_camera_x and lock_camera_y",
+                    object.x, object.y
+                )))
+            }
+        }
+        if let Some(image) = object.properties.get("message") {
+            actions.push(TriggerAction::ShowMessage {
+                image: resolve_path(base, image),
+            });
+        }
+
+        Ok(TriggerDefinition {
+            x: area.x,
+            y: area.y,
+            width: area.width,
+            height: area.height,
+            actions,
+            once: object_number(object, "once")?.unwrap_or(false),
+        })
+    }
+
+    // 動く足場はポリラインで書き、頂点を足場の左端が通る順に巡回する
+    fn object_moving_platform(
+        &self,
+        object: &TiledObject,
+        base: &Path,
+    ) -> Result<MovingPlatformDefinition, TiledLoadError> {
+        if object.points.is_empty() {
+            return Err(TiledLoadError::Invalid(format!(
+                "moving_platform object at ({}, {}) is not a polyline",
+                object.x, object.y
+            )));
+        }
+        let path = object
+            .points
+            .iter()
+            .map(|(x, y)| {
+                Ok(TilePosition {
+                    x: tile_index(object, object.x + x, self.tile_width)?,
+                    y: tile_index(object, object.y + y, self.tile_height)?,
+                })
+            })
+            .collect::<Result<Vec<_>, TiledLoadError>>()?;
+        Ok(MovingPlatformDefinition {
+            image: object_image(object, base)?,
+            width: object_number(object, "width")?.unwrap_or_else(default_platform_width),
+            path,
+            speed: object_number(object, "speed")?.unwrap_or_else(default_platform_speed),
+        })
+    }
+
+    // 崩れる足場は足場のタイルを覆う矩形で書く（高さは1タイル分だけ使う）
+    fn object_crumbling_platform(
+        &self,
+        object: &TiledObject,
+        base: &Path,
+    ) -> Result<CrumblingPlatformDefinition, TiledLoadError> {
+        let area = self.object_tile_area(object)?;
+        Ok(CrumblingPlatformDefinition {
+            x: area.x,
+            y: area.y,
+            image: object_image(object, base)?,
+            width: area.width,
+            crumble_time: object_number(object, "crumble_time")?
+                .unwrap_or_else(default_crumble_time),
+            respawn_time: object_number(object, "respawn_time")?
+                .unwrap_or_else(default_respawn_time),
+        })
+    }
+
+    // オブジェクトが覆っている範囲（点なら1タイル）
+    fn object_tile_area(&self, object: &TiledObject) -> Result<TileArea, TiledLoadError> {
+        let area = self.object_area(object)?;
+        let (first, last) = (area[0], area[area.len() - 1]);
+        Ok(TileArea {
+            x: first.x,
+            y: first.y,
+            width: last.x - first.x + 1,
+            height: last.y - first.y + 1,
+        })
+    }
+
+    // オブジェクトが覆っているタイルすべて（点なら1タイル）
+    fn object_area(&self, object: &TiledObject) -> Result<Vec<TilePosition>, TiledLoadError> {
+        if object.width <= 0. || object.height <= 0. {
+            return Ok(vec![self.object_position(object)?]);
+        }
+        let top = self.object_top(object);
+        let first_x = tile_index(object, object.x, self.tile_width)?;
+        let first_y = tile_index(object, top, self.tile_height)?;
+        let last_x = tile_index(object, object.x + object.width - 1., self.tile_width)?;
+        let last_y = tile_index(object, top + object.height - 1., self.tile_height)?;
+
+        let mut positions = Vec::new();
+        for y in first_y..=last_y {
+            for x in first_x..=last_x {
+                positions.push(TilePosition { x, y });
+            }
+        }
+        Ok(positions)
+    }
+}
+
+fn tile_index(object: &TiledObject, pixel: f32, tile_size: u32) -> Result<u32, TiledLoadError> {
+    let index = (pixel / tile_size as f32).floor();
+    if index < 0. {
+        return Err(TiledLoadError::Invalid(format!(
+            "{} object at ({}, {}) is outside of the map",
+            object.kind, object.x, object.y
+        )));
+    }
+    Ok(index as u32)
+}
+
+fn external_tileset_error(source: &str) -> TiledLoadError {
+    TiledLoadError::Invalid(format!(
+        "external tileset '{}' is not supported, embed it in the map",
+        source
+    ))
+}
+
+// オブジェクトの数値のプロパティ（省略できる）
+fn object_number<T: FromStr>(
+    object: &TiledObject,
+    name: &str,
+) -> Result<Option<T>, TiledLoadError> {
+    object
+        .properties
+        .get(name)
+        .map(|value| {
+            value
+                .parse::<T>()
+                .map_err(|_| invalid_property(object, name, value))
+        })
+        .transpose()
+}
+
+// 足場の画像はimageプロパティで指定する（マップファイルからの相対パス）
+fn object_image(object: &TiledObject, base: &Path) -> Result<String, TiledLoadError> {
+    object
+        .properties
+        .get("image")
+        .map(|image| resolve_path(base, image))
+        .ok_or_else(|| {
+            TiledLoadError::Invalid(format!(
+                "{} object at ({}, {}) has no image property",
+                object.kind, object.x, object.y
+            ))
+        })
+}
+
+fn invalid_property(object: &TiledObject, name: &str, value: &str) -> TiledLoadError {
+    TiledLoadError::Invalid(format!(
+        "{} object at ({}, {}) has invalid {} '{}'",
+        object.kind, object.x, object.y, name, value
+    ))
+}
+
+// 同じマスに複数のレイヤーのタイルがあるときの優先度
+fn priority(kind: TileKind) -> u8 {
+    match kind {
+        TileKind::Background => 0,
+        TileKind::Platform | TileKind::Spike | TileKind::Ladder => 1,
+        TileKind::Wall => 2,
+    }
+}
+
+// マップファイルのあるディレクトリからの相対パスをassetsからのパスにする
+fn resolve_path(base: &Path, relative: &str) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in base.join(relative).components() {
+        match component {
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            _ => {}
+        }
+    }
+    parts.join("/")
+}
+
+fn xml_number(node: roxmltree::Node, name: &str) -> Result<u32, TiledLoadError> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            TiledLoadError::Invalid(format!(
+                "<{}> has no valid '{}' attribute",
+                node.tag_name().name(),
+                name
+            ))
+        })
+}
+
+fn xml_float(node: roxmltree::Node, name: &str) -> f32 {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.)
+}
+
+// <polyline points="0,0 64,0"/>の頂点
+fn xml_points(node: roxmltree::Node) -> Vec<(f32, f32)> {
+    node.children()
+        .find(|child| child.has_tag_name("polyline"))
+        .and_then(|polyline| polyline.attribute("points"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|point| {
+            let (x, y) = point.split_once(',')?;
+            Some((x.parse().ok()?, y.parse().ok()?))
+        })
+        .collect()
+}
+
+fn xml_properties(node: roxmltree::Node) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    for property in node
+        .children()
+        .filter(|child| child.has_tag_name("properties"))
+        .flat_map(|child| child.children())
+        .filter(|child| child.has_tag_name("property"))
+    {
+        let Some(name) = property.attribute("name") else {
+            continue;
+        };
+        // 複数行の文字列はvalue属性ではなく中身に入っている
+        let value = property
+            .attribute("value")
+            .or(property.text())
+            .unwrap_or_default();
+        properties.insert(name.to_string(), value.to_string());
+    }
+    properties
+}
+
+fn xml_tileset(node: roxmltree::Node) -> Result<TiledTileset, TiledLoadError> {
+    if let Some(source) = node.attribute("source") {
+        return Err(external_tileset_error(source));
+    }
+    let mut tiles = HashMap::new();
+    for tile in node.children().filter(|child| child.has_tag_name("tile")) {
+        let image = tile
+            .children()
+            .find(|child| child.has_tag_name("image"))
+            .and_then(|image| image.attribute("source"))
+            .map(|source| source.to_string());
+        tiles.insert(
+            xml_number(tile, "id")?,
+            TiledTile {
+                image,
+                properties: xml_properties(tile),
+            },
+        );
+    }
+    Ok(TiledTileset {
+        first_gid: xml_number(node, "firstgid")?,
+        tiles,
+    })
+}
+
+fn xml_layers(node: roxmltree::Node, layers: &mut Vec<TiledLayer>) -> Result<(), TiledLoadError> {
+    for child in node.children().filter(|child| child.is_element()) {
+        match child.tag_name().name() {
+            "layer" => {
+                let name = child.attribute("name").unwrap_or_default().to_string();
+                let Some(data) = child.children().find(|data| data.has_tag_name("data")) else {
+                    return Err(TiledLoadError::Invalid(format!(
+                        "layer '{}' has no <data>",
+                        name
+                    )));
+                };
+                let data = match data.attribute("encoding") {
+                    Some("csv") => data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|gid| gid.trim())
+                        .filter(|gid| !gid.is_empty())
+                        .map(|gid| gid.parse::<u32>())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| {
+                            TiledLoadError::Invalid(format!(
+                                "layer '{}' has invalid csv data",
+                                name
+                            ))
+                        })?,
+                    None => data
+                        .children()
+                        .filter(|tile| tile.has_tag_name("tile"))
+                        .map(|tile| xml_number(tile, "gid").unwrap_or(0))
+                        .collect(),
+                    Some(encoding) => {
+                        return Err(TiledLoadError::Invalid(format!(
+                            "layer '{}' uses {} encoding, save the map with CSV layer format",
+                            name, encoding
+                        )))
+                    }
+                };
+                layers.push(TiledLayer::Tiles { name, data });
+            }
+            "objectgroup" => {
+                let objects = child
+                    .children()
+                    .filter(|object| object.has_tag_name("object"))
+                    .map(|object| TiledObject {
+                        kind: object
+                            .attribute("class")
+                            .or(object.attribute("type"))
+                            .or(object.attribute("name"))
+                            .unwrap_or_default()
+                            .to_string(),
+                        x: xml_float(object, "x"),
+                        y: xml_float(object, "y"),
+                        width: xml_float(object, "width"),
+                        height: xml_float(object, "height"),
+                        gid: object
+                            .attribute("gid")
+                            .and_then(|gid| gid.parse::<u32>().ok()),
+                        points: xml_points(object),
+                        properties: xml_properties(object),
+                    })
+                    .collect();
+                layers.push(TiledLayer::Objects { objects });
+            }
+            // グループレイヤーの中身も順番通りに読む
+            "group" => xml_layers(child, layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    image: Option<String>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    gid: Option<u32>,
+    #[serde(default)]
+    polyline: Vec<JsonPoint>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+fn json_properties(properties: Vec<JsonProperty>) -> HashMap<String, String> {
+    properties
+        .into_iter()
+        .map(|property| {
+            let value = match property.value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            (property.name, value)
+        })
+        .collect()
+}
+
+fn json_layers(source: Vec<JsonLayer>, layers: &mut Vec<TiledLayer>) -> Result<(), TiledLoadError> {
+    for layer in source {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let data = match layer.data {
+                    Some(serde_json::Value::Array(gids)) => gids
+                        .iter()
+                        .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                        .collect::<Option<Vec<u32>>>()
+                        .ok_or_else(|| {
+                            TiledLoadError::Invalid(format!(
+                                "layer '{}' has invalid data",
+                                layer.name
+                            ))
+                        })?,
+                    _ => {
+                        return Err(TiledLoadError::Invalid(format!(
+                            "layer '{}' is not saved as a CSV array, save the map with CSV layer format",
+                            layer.name
+                        )))
+                    }
+                };
+                layers.push(TiledLayer::Tiles {
+                    name: layer.name,
+                    data,
+                });
+            }
+            "objectgroup" => {
+                let objects = layer
+                    .objects
+                    .into_iter()
+                    .map(|object| TiledObject {
+                        kind: [object.class, object.kind, object.name]
+                            .into_iter()
+                            .find(|kind| !kind.is_empty())
+                            .unwrap_or_default(),
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        gid: object.gid,
+                        points: object
+                            .polyline
+                            .iter()
+                            .map(|point| (point.x, point.y))
+                            .collect(),
+                        properties: json_properties(object.properties),
+                    })
+                    .collect();
+                layers.push(TiledLayer::Objects { objects });
+            }
+            // グループレイヤーの中身も順番通りに読む
+            "group" => json_layers(layer.layers, layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(objects: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="6" height="3" tilewidth="32" tileheight="32" infinite="0">
+ <properties>
+  <property name="background" value="../images/map/map_1.png"/>
+ </properties>
+ <layer id="1" name="tiles" width="6" height="3">
+  <data encoding="csv">0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" type="player_start" x="0" y="64" width="32" height="32"/>
+{}
+ </objectgroup>
+</map>"#,
+            objects
+        )
+    }
+
+    fn load(objects: &str) -> Result<StageData, TiledLoadError> {
+        stage_from_tiled(map(objects).as_bytes(), Path::new("stages/test.tmx"))
+    }
+
+    #[test]
+    fn imports_platforms() {
+        let stage = load(
+            r#"  <object id="2" type="moving_platform" x="32" y="32">
+   <properties>
+    <property name="image" value="../images/map/platform.png"/>
+    <property name="width" type="int" value="2"/>
+   </properties>
+   <polyline points="0,0 96,0"/>
+  </object>
+  <object id="3" type="crumbling_platform" x="64" y="64" width="64" height="32">
+   <properties>
+    <property name="image" value="../images/map/platform.png"/>
+    <property name="respawn_time" type="float" value="5"/>
+   </properties>
+  </object>"#,
+        )
+        .unwrap();
+
+        let moving = &stage.moving_platforms[0];
+        assert_eq!(moving.image, "images/map/platform.png");
+        assert_eq!(moving.width, 2);
+        assert_eq!(
+            moving
+                .path
+                .iter()
+                .map(|position| (position.x, position.y))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (4, 1)]
+        );
+        let crumbling = &stage.crumbling_platforms[0];
+        assert_eq!((crumbling.x, crumbling.y, crumbling.width), (2, 2, 2));
+        assert_eq!(crumbling.respawn_time, 5.);
+    }
+
+    #[test]
+    fn moving_platform_needs_polyline() {
+        let result = load(
+            r#"  <object id="2" type="moving_platform" x="32" y="32" width="64" height="32">
+   <properties>
+    <property name="image" value="../images/map/platform.png"/>
+   </properties>
+  </object>"#,
+        );
+        assert!(matches!(result, Err(TiledLoadError::Invalid(_))));
+    }
+
+    #[test]
+    fn imports_boss_arena() {
+        let boss = r#"  <object id="2" type="boss_spawn" x="128" y="64" width="32" height="32">
+   <properties>
+    <property name="wall_image" value="../images/map/map_3.png"/>
+   </properties>
+  </object>"#;
+        let respawn =
+            r#"  <object id="3" type="boss_respawn" x="32" y="64" width="32" height="32"/>"#;
+        let stage = load(&format!("{}\n{}", boss, respawn)).unwrap();
+
+        let arena = stage.boss_arena.unwrap();
+        assert_eq!((arena.boss.x, arena.boss.y), (4, 2));
+        assert_eq!((arena.respawn.x, arena.respawn.y), (1, 2));
+        assert_eq!(arena.wall_image, "images/map/map_3.png");
+
+        // 壁の画像は省略できるが、リスポーン位置は省略できない
+        let stage = load(&format!(
+            "{}\n{}",
+            r#"  <object id="2" type="boss_spawn" x="128" y="64" width="32" height="32"/>"#,
+            respawn
+        ))
+        .unwrap();
+        assert_eq!(
+            stage.boss_arena.unwrap().wall_image,
+            default_boss_wall_image()
+        );
+        assert!(matches!(load(boss), Err(TiledLoadError::Invalid(_))));
+    }
+
+    // 同じマップをtmxとtmjで書いたもの。下のレイヤーの壁は上のレイヤーの背景より優先し、
+    // 反転フラグ付きのgidも同じタイルになる
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="6" height="4" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="tiles" tilewidth="32" tileheight="32" tilecount="3" columns="0">
+  <tile id="0">
+   <image width="32" height="32" source="../../images/map/map_1.png"/>
+  </tile>
+  <tile id="1">
+   <properties>
+    <property name="kind" value="wall"/>
+   </properties>
+   <image width="32" height="32" source="../../images/map/map_3.png"/>
+  </tile>
+  <tile id="2">
+   <properties>
+    <property name="kind" value="platform"/>
+   </properties>
+   <image width="32" height="32" source="../../images/map/platform.png"/>
+  </tile>
+ </tileset>
+ <layer id="1" name="back" width="6" height="4">
+  <data encoding="csv">
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+2,2,2147483650,2,2,2
+</data>
+ </layer>
+ <layer id="2" name="front" width="6" height="4">
+  <data encoding="csv">
+0,0,0,0,0,0,
+0,0,3,3,0,0,
+0,0,0,0,0,0,
+1,0,0,0,0,0
+</data>
+ </layer>
+ <objectgroup id="3" name="objects">
+  <object id="1" type="player_start" x="0" y="64" width="32" height="32"/>
+  <object id="2" class="goal" gid="1" x="160" y="96" width="32" height="32"/>
+  <object id="3" type="enemy" x="96" y="64" width="32" height="32">
+   <properties>
+    <property name="kind" value="slime"/>
+    <property name="facing" value="left"/>
+   </properties>
+  </object>
+  <object id="4" type="enemy" x="128" y="64" width="32" height="32">
+   <properties>
+    <property name="kind" value="lizard"/>
+    <property name="spawn_group" value="wave"/>
+   </properties>
+  </object>
+  <object id="5" type="trigger" x="64" y="0" width="64" height="128">
+   <properties>
+    <property name="message" value="../../images/scene/message.png"/>
+    <property name="once" type="bool" value="true"/>
+    <property name="spawn_group" value="wave"/>
+    <property name="start_boss" type="bool" value="true"/>
+   </properties>
+  </object>
+  <object id="6" type="boss_spawn" x="128" y="32" width="32" height="32"/>
+  <object id="7" type="boss_respawn" x="32" y="64" width="32" height="32"/>
+ </objectgroup>
+</map>"#;
+
+    const TMJ: &str = r#"{
+ "width": 6, "height": 4, "tilewidth": 32, "tileheight": 32, "infinite": false,
+ "tilesets": [
+  {
+   "firstgid": 1, "name": "tiles",
+   "tiles": [
+    {"id": 0, "image": "../../images/map/map_1.png"},
+    {"id": 1, "image": "../../images/map/map_3.png",
+     "properties": [{"name": "kind", "type": "string", "value": "wall"}]},
+    {"id": 2, "image": "../../images/map/platform.png",
+     "properties": [{"name": "kind", "type": "string", "value": "platform"}]}
+   ]
+  }
+ ],
+ "layers": [
+  {"type": "tilelayer", "name": "back", "width": 6, "height": 4, "data": [
+   1, 1, 1, 1, 1, 1,
+   1, 1, 1, 1, 1, 1,
+   1, 1, 1, 1, 1, 1,
+   2, 2, 2147483650, 2, 2, 2
+  ]},
+  {"type": "tilelayer", "name": "front", "width": 6, "height": 4, "data": [
+   0, 0, 0, 0, 0, 0,
+   0, 0, 3, 3, 0, 0,
+   0, 0, 0, 0, 0, 0,
+   1, 0, 0, 0, 0, 0
+  ]},
+  {"type": "objectgroup", "name": "objects", "objects": [
+   {"type": "player_start", "x": 0, "y": 64, "width": 32, "height": 32},
+   {"class": "goal", "gid": 1, "x": 160, "y": 96, "width": 32, "height": 32},
+   {"type": "enemy", "x": 96, "y": 64, "width": 32, "height": 32, "properties": [
+    {"name": "kind", "type": "string", "value": "slime"},
+    {"name": "facing", "type": "string", "value": "left"}
+   ]},
+   {"type": "enemy", "x": 128, "y": 64, "width": 32, "height": 32, "properties": [
+    {"name": "kind", "type": "string", "value": "lizard"},
+    {"name": "spawn_group", "type": "string", "value": "wave"}
+   ]},
+   {"type": "trigger", "x": 64, "y": 0, "width": 64, "height": 128, "properties": [
+    {"name": "message", "type": "string", "value": "../../images/scene/message.png"},
+    {"name": "once", "type": "bool", "value": true},
+    {"name": "spawn_group", "type": "string", "value": "wave"},
+    {"name": "start_boss", "type": "bool", "value": true}
+   ]},
+   {"type": "boss_spawn", "x": 128, "y": 32, "width": 32, "height": 32},
+   {"type": "boss_respawn", "x": 32, "y": 64, "width": 32, "height": 32}
+  ]}
+ ]
+}"#;
+
+    #[test]
+    fn tmx_and_tmj_give_the_same_stage() {
+        let tmx = stage_from_tiled(TMX.as_bytes(), Path::new("stages/tiled/test.tmx")).unwrap();
+        let tmj = stage_from_tiled(TMJ.as_bytes(), Path::new("stages/tiled/test.tmj")).unwrap();
+        assert_eq!(tmx.to_ron().unwrap(), tmj.to_ron().unwrap());
+
+        // 壁、足場、背景の順に優先する
+        let rows: Vec<String> = tmx.map.iter().map(|row| row.iter().collect()).collect();
+        assert_eq!(rows, vec!["AAAAAA", "AABBAA", "AAAAAA", "CCCCCC"]);
+        let tile = |symbol: char| {
+            let tile = &tmx.legend[&symbol];
+            (tile.kind, tile.image.as_str())
+        };
+        assert_eq!(tile('A'), (TileKind::Background, "images/map/map_1.png"));
+        assert_eq!(tile('B'), (TileKind::Platform, "images/map/platform.png"));
+        assert_eq!(tile('C'), (TileKind::Wall, "images/map/map_3.png"));
+
+        assert_eq!((tmx.player_start.x, tmx.player_start.y), (0, 2));
+        // タイルオブジェクトのyは下端
+        assert_eq!(tmx.goal.map(|goal| (goal.x, goal.y)), Some((5, 2)));
+        assert_eq!(tmx.enemies.len(), 1);
+        let enemy = tmx.enemies[0];
+        assert_eq!((enemy.x, enemy.y), (3, 2));
+        assert_eq!((enemy.kind, enemy.facing), (EnemyKind::Slime, Facing::Left));
+
+        let trigger = &tmx.triggers[0];
+        assert_eq!(
+            (trigger.x, trigger.y, trigger.width, trigger.height),
+            (2, 0, 2, 4)
+        );
+        assert!(trigger.once);
+        assert!(matches!(
+            trigger.actions.as_slice(),
+            [
+                TriggerAction::StartBoss,
+                TriggerAction::ShowMessage { image },
+                TriggerAction::SpawnEnemies(enemies),
+            ] if image == "images/scene/message.png"
+                && enemies.len() == 1
+                && enemies[0].kind == EnemyKind::Lizard
+        ));
+
+        let arena = tmx.boss_arena.unwrap();
+        assert_eq!((arena.boss.x, arena.boss.y), (4, 1));
+        assert_eq!((arena.respawn.x, arena.respawn.y), (1, 2));
+    }
+
+    #[test]
+    fn spawn_group_needs_both_enemy_and_trigger() {
+        let enemy = r#"  <object id="2" type="enemy" x="32" y="64" width="32" height="32">
+   <properties>
+    <property name="kind" value="slime"/>
+    <property name="spawn_group" value="wave"/>
+   </properties>
+  </object>"#;
+        let trigger = r#"  <object id="3" type="trigger" x="64" y="0" width="32" height="96">
+   <properties>
+    <property name="spawn_group" value="wave"/>
+   </properties>
+  </object>"#;
+        assert!(matches!(load(enemy), Err(TiledLoadError::Invalid(_))));
+        assert!(matches!(load(trigger), Err(TiledLoadError::Invalid(_))));
+        let stage = load(&format!("{}\n{}", enemy, trigger)).unwrap();
+        assert!(stage.enemies.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_gid() {
+        let source = map("").replacen("0,", "9,", 1);
+        let result = stage_from_tiled(source.as_bytes(), Path::new("stages/test.tmx"));
+        assert!(matches!(
+            result,
+            Err(TiledLoadError::UnknownTile {
+                row: 0,
+                column: 0,
+                gid: 9,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_player_start() {
+        let source = TMX.replace(
+            r#"  <object id="1" type="player_start" x="0" y="64" width="32" height="32"/>"#,
+            "",
+        );
+        let result = stage_from_tiled(source.as_bytes(), Path::new("stages/tiled/test.tmx"));
+        assert!(matches!(result, Err(TiledLoadError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_base64_layers() {
+        let source = map("").replace(r#"encoding="csv""#, r#"encoding="base64""#);
+        let result = stage_from_tiled(source.as_bytes(), Path::new("stages/test.tmx"));
+        assert!(matches!(result, Err(TiledLoadError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_external_tilesets() {
+        let source = map("").replace(
+            " <layer",
+            " <tileset firstgid=\"1\" source=\"tiles.tsx\"/>\n <layer",
+        );
+        let result = stage_from_tiled(source.as_bytes(), Path::new("stages/test.tmx"));
+        assert!(matches!(result, Err(TiledLoadError::Invalid(_))));
+
+        let source = TMJ.replace(
+            r#""firstgid": 1,"#,
+            r#""firstgid": 1, "source": "tiles.tsj","#,
+        );
+        let result = stage_from_tiled(source.as_bytes(), Path::new("stages/tiled/test.tmj"));
+        assert!(matches!(result, Err(TiledLoadError::Invalid(_))));
+    }
+}
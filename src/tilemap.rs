@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+
+use crate::stage::{LayerDefinition, StageData, TileDefinition, TileKind};
+
+// 1つのメッシュにまとめるタイルの範囲（列数、行数）
+pub const CHUNK_SIZE: usize = 16;
+
+// 上、右、下、左のタイルの(列, 上から数えた行)のずれと、つながっているときに絵の番号に足す値
+const NEIGHBORS: [(isize, isize, u32); 4] = [(0, -1, 1), (1, 0, 2), (0, 1, 4), (-1, 0, 8)];
+
+// チャンクの中で同じ定義のタイル。1つのメッシュとして描く
+#[derive(Clone, Debug)]
+pub struct TileBatch {
+    pub chunk: UVec2,
+    pub z: f32,
+    pub tile: TileDefinition,
+    pub tiles: Vec<BatchTile>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchTile {
+    // (列, 下から数えた行)
+    pub position: UVec2,
+    // オートタイルの絵の番号（オートタイルでなければ0）
+    pub variant: u32,
+}
+
+impl TileBatch {
+    // タイルの中心が(tile_size * 列, tile_size * 行)になる四角形を並べたメッシュ
+    // frameはアニメーションのコマ
+    pub fn mesh(&self, tile_size: f32, frame: u32) -> Mesh {
+        let half = tile_size / 2.;
+        let grid = self.tile.grid().as_vec2();
+        let mut positions = Vec::with_capacity(self.tiles.len() * 4);
+        let mut uvs = Vec::with_capacity(self.tiles.len() * 4);
+        let mut indices = Vec::with_capacity(self.tiles.len() * 6);
+        for tile in self.tiles.iter() {
+            let center = tile.position.as_vec2() * tile_size;
+            let first = positions.len() as u32;
+            positions.extend([
+                [center.x - half, center.y - half, 0.],
+                [center.x + half, center.y - half, 0.],
+                [center.x + half, center.y + half, 0.],
+                [center.x - half, center.y + half, 0.],
+            ]);
+            // コマは横、オートタイルの絵は縦に並んでいる（画像は上の行から）
+            let left = frame as f32 / grid.x;
+            let right = (frame + 1) as f32 / grid.x;
+            let top = tile.variant as f32 / grid.y;
+            let bottom = (tile.variant + 1) as f32 / grid.y;
+            uvs.extend([[left, bottom], [right, bottom], [right, top], [left, top]]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+        let normals = vec![[0., 0., 1.]; positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+// タイルが入るチャンク
+pub fn chunk_of(column: usize, row: usize) -> UVec2 {
+    UVec2::new((column / CHUNK_SIZE) as u32, (row / CHUNK_SIZE) as u32)
+}
+
+// マップのタイルをチャンクと定義ごとにまとめる（chunkを指定したらそのチャンクだけ）
+// 背景はキャラクターより奥(z=-1)、それ以外（壁など）は背景より手前(z=0)
+pub fn tile_batches(stage: &StageData, chunk: Option<UVec2>) -> Vec<TileBatch> {
+    let kinds = stage
+        .map
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|tile| Some(stage.legend[tile].kind))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    group(
+        stage
+            .tiles()
+            .filter(|(column, row, _)| chunk.is_none() || chunk == Some(chunk_of(*column, *row))),
+        &kinds,
+        |tile| {
+            if tile.kind == TileKind::Background {
+                -1.
+            } else {
+                0.
+            }
+        },
+    )
+}
+
+// 飾りのレイヤーのタイルをチャンクと定義ごとにまとめる
+pub fn layer_batches(stage: &StageData, layer: &LayerDefinition) -> Vec<TileBatch> {
+    let kinds = layer
+        .map
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|tile| stage.legend.get(&tile).map(|tile| tile.kind))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    group(stage.layer_tiles(layer), &kinds, |_| layer.z)
+}
+
+// (列, 上から数えた行)のタイルのオートタイルの絵の番号
+// kindsは上の行から並べたタイルの種類（Noneは何も置いていない）。外側はつながっている扱い
+pub fn autotile_variant(kinds: &[Vec<Option<TileKind>>], column: usize, row: usize) -> u32 {
+    let kind = kinds[row][column];
+    NEIGHBORS
+        .iter()
+        .filter(|(dx, dy, _)| {
+            let neighbor = row
+                .checked_add_signed(*dy)
+                .and_then(|row| kinds.get(row))
+                .and_then(|tiles| tiles.get(column.checked_add_signed(*dx)?));
+            neighbor.is_none() || neighbor == Some(&kind)
+        })
+        .map(|(_, _, bit)| bit)
+        .sum()
+}
+
+fn group<'a>(
+    tiles: impl Iterator<Item = (usize, usize, &'a TileDefinition)>,
+    kinds: &[Vec<Option<TileKind>>],
+    z: impl Fn(&TileDefinition) -> f32,
+) -> Vec<TileBatch> {
+    // zは順番を決めるためだけにビット列にする
+    let mut batches: BTreeMap<_, (&TileDefinition, Vec<BatchTile>)> = BTreeMap::new();
+    for (column, row, tile) in tiles {
+        let chunk = chunk_of(column, row);
+        let variant = if tile.autotile {
+            autotile_variant(kinds, column, kinds.len() - 1 - row)
+        } else {
+            0
+        };
+        let animation = tile
+            .animation
+            .map(|animation| (animation.frames, animation.frame_time.to_bits()));
+        batches
+            .entry((
+                chunk.x,
+                chunk.y,
+                z(tile).to_bits(),
+                tile.image.as_str(),
+                tile.autotile,
+                animation,
+            ))
+            .or_insert_with(|| (tile, Vec::new()))
+            .1
+            .push(BatchTile {
+                position: UVec2::new(column as u32, row as u32),
+                variant,
+            });
+    }
+    batches
+        .into_iter()
+        .map(|((x, y, z, ..), (tile, tiles))| TileBatch {
+            chunk: UVec2::new(x, y),
+            z: f32::from_bits(z),
+            tile: tile.clone(),
+            tiles,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage::{test_stage, TileAnimation};
+
+    fn stage(width: usize, height: usize) -> StageData {
+        let map = (0..height)
+            .map(|row| {
+                (0..width)
+                    .map(|column| {
+                        if row == height - 1 {
+                            '#'
+                        } else if column % 2 == 0 {
+                            '.'
+                        } else {
+                            ','
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let map = map.iter().map(|row| row.as_str()).collect::<Vec<_>>();
+        test_stage("player_start: (x: 1, y: 0), enemies: [],", &map)
+    }
+
+    #[test]
+    fn groups_tiles_by_chunk_and_image() {
+        let stage = stage(40, 15);
+        let batches = tile_batches(&stage, None);
+        // 3チャンク × (背景2種類 + 壁)
+        assert_eq!(batches.len(), 9);
+        assert_eq!(
+            batches.iter().map(|batch| batch.tiles.len()).sum::<usize>(),
+            40 * 15
+        );
+        let walls = batches
+            .iter()
+            .filter(|batch| batch.z == 0.)
+            .collect::<Vec<_>>();
+        assert_eq!(walls.len(), 3);
+        assert!(walls
+            .iter()
+            .all(|batch| batch.tiles.iter().all(|tile| tile.position.y == 0)));
+        assert_eq!(walls[2].chunk, UVec2::new(2, 0));
+        assert_eq!(walls[2].tiles.len(), 40 - 2 * CHUNK_SIZE);
+    }
+
+    #[test]
+    fn selects_one_chunk() {
+        let stage = stage(40, 15);
+        let batches = tile_batches(&stage, Some(UVec2::new(1, 0)));
+        assert_eq!(batches.len(), 3);
+        assert!(batches.iter().all(|batch| batch.chunk == UVec2::new(1, 0)
+            && batch
+                .tiles
+                .iter()
+                .all(|tile| (16..32).contains(&tile.position.x))));
+    }
+
+    #[test]
+    fn layer_skips_blank_tiles() {
+        let stage = stage(40, 15);
+        let layer = LayerDefinition {
+            z: 5.,
+            scroll: 1.5,
+            scroll_y: None,
+            map: vec!["#  #".to_string(), ". , ".to_string()],
+        };
+        let batches = layer_batches(&stage, &layer);
+        assert_eq!(batches.len(), 3);
+        assert!(batches.iter().all(|batch| batch.z == 5.));
+        let walls = batches
+            .iter()
+            .find(|batch| batch.tile.image == "images/map/map_3.png")
+            .unwrap();
+        assert_eq!(
+            walls
+                .tiles
+                .iter()
+                .map(|tile| tile.position)
+                .collect::<Vec<_>>(),
+            vec![UVec2::new(0, 1), UVec2::new(3, 1)]
+        );
+    }
+
+    #[test]
+    fn autotile_connects_to_same_kind() {
+        let wall = Some(TileKind::Wall);
+        let background = Some(TileKind::Background);
+        let kinds = vec![
+            vec![background, background, background],
+            vec![wall, wall, background],
+            vec![wall, wall, wall],
+        ];
+        // 右と下
+        assert_eq!(autotile_variant(&kinds, 0, 1), 2 + 4 + 8);
+        // 左と下（右は背景、上も背景）
+        assert_eq!(autotile_variant(&kinds, 1, 1), 4 + 8);
+        // マップの外は下も右もつながっている
+        assert_eq!(autotile_variant(&kinds, 2, 2), 2 + 4 + 8);
+        // 何も置いていない所とはつながらない
+        assert_eq!(autotile_variant(&[vec![wall, None, wall]], 0, 0), 1 + 4 + 8);
+    }
+
+    #[test]
+    fn autotiled_walls_get_variants() {
+        let mut stage = stage(4, 3);
+        for tile in stage.legend.values_mut() {
+            tile.autotile = true;
+        }
+        let batches = tile_batches(&stage, None);
+        let walls = batches.iter().find(|batch| batch.z == 0.).unwrap();
+        // 一番下の行の壁は左右と下がつながり、上は背景
+        assert!(walls.tiles.iter().all(|tile| tile.variant == 2 + 4 + 8));
+    }
+
+    #[test]
+    fn mesh_has_a_quad_per_tile() {
+        let batch = TileBatch {
+            chunk: UVec2::ZERO,
+            z: 0.,
+            tile: TileDefinition {
+                kind: TileKind::Wall,
+                image: "images/map/map_3.png".to_string(),
+                autotile: false,
+                animation: None,
+            },
+            tiles: vec![
+                BatchTile {
+                    position: UVec2::new(0, 0),
+                    variant: 0,
+                },
+                BatchTile {
+                    position: UVec2::new(2, 1),
+                    variant: 0,
+                },
+            ],
+        };
+        let mesh = batch.mesh(32., 0);
+        assert_eq!(mesh.count_vertices(), 8);
+        assert_eq!(mesh.indices().unwrap().len(), 12);
+        let Some(bevy::render::mesh::VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("positions are Float32x3");
+        };
+        assert_eq!(positions[4], [48., 16., 0.]);
+        assert_eq!(positions[6], [80., 48., 0.]);
+    }
+
+    #[test]
+    fn mesh_uses_frame_and_variant() {
+        let batch = TileBatch {
+            chunk: UVec2::ZERO,
+            z: 0.,
+            tile: TileDefinition {
+                kind: TileKind::Background,
+                image: "images/map/water.png".to_string(),
+                autotile: true,
+                animation: Some(TileAnimation {
+                    frames: 4,
+                    frame_time: 0.25,
+                }),
+            },
+            tiles: vec![BatchTile {
+                position: UVec2::new(1, 1),
+                variant: 15,
+            }],
+        };
+        let mesh = batch.mesh(32., 2);
+        let Some(bevy::render::mesh::VertexAttributeValues::Float32x2(uvs)) =
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("uvs are Float32x2");
+        };
+        // 左下と右上
+        assert_eq!(uvs[0], [0.5, 1.]);
+        assert_eq!(uvs[2], [0.75, 15. / 16.]);
+    }
+}
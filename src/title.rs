@@ -1,10 +1,12 @@
+use try_rust_bevy::campaign::*;
 use try_rust_bevy::consts::*;
 use try_rust_bevy::utils::*;
 
 pub mod title_scene {
     use bevy::prelude::*;
 
-    use super::{despawn_screen, GameState};
+    use super::{despawn_screen, Campaign, CampaignProgress, CurrentCampaign, GameState};
+    use crate::editor::editor_scene::EditorSession;
 
     pub struct TitlePlugin;
 
@@ -31,11 +33,16 @@ pub mod title_scene {
         ));
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn control_keys(
+        mut commands: Commands,
         mut game_state: ResMut<NextState<GameState>>,
         keyboard_input: Res<Input<KeyCode>>,
         gamepads: Res<Gamepads>,
         button_inputs: Res<Input<GamepadButton>>,
+        mut progress: ResMut<CampaignProgress>,
+        campaigns: Res<Assets<Campaign>>,
+        current_campaign: Res<CurrentCampaign>,
     ) {
         let mut pressed = keyboard_input.just_pressed(KeyCode::Z);
         for gamepad in gamepads.iter() {
@@ -43,7 +50,22 @@ pub mod title_scene {
                 pressed = true;
             }
         }
+        let editor = keyboard_input.just_pressed(KeyCode::E);
+        if !pressed && !editor {
+            return;
+        }
+        // キャンペーンの読み込みに失敗していたら始められない
+        let Some(campaign) = campaigns.get(&current_campaign.handle) else {
+            error!("the campaign is not loaded, fix the campaign file and restart");
+            return;
+        };
         if pressed {
+            // 残機を最初の数にして始める
+            *progress = CampaignProgress::new(campaign);
+            game_state.set(GameState::Loading);
+        } else {
+            // ステージエディタで開く
+            commands.insert_resource(EditorSession::default());
             game_state.set(GameState::Loading);
         }
     }
@@ -0,0 +1,229 @@
+use std::fmt;
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::collision::CollisionMap;
+use crate::consts::*;
+use crate::reachability::Reachability;
+use crate::stage::{
+    map_errors, EnemyKind, EnemyPosition, StageData, StageFile, StageLoadError, TilePosition,
+    TriggerAction,
+};
+use crate::tiled::{asset_path, is_tiled_map, stage_from_tiled};
+
+// ステージデータの問題点
+#[derive(Debug)]
+pub enum StageIssue {
+    // 読み込めない（行の長さや未定義のタイルは1つずつ報告する）
+    Load(String),
+    InsideWall { what: String, x: u32, y: u32 },
+    // 歩く敵の足元に床がない
+    Floating { what: String, x: u32, y: u32 },
+    GoalUnreachable,
+}
+
+impl fmt::Display for StageIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageIssue::Load(error) => write!(f, "{}", error),
+            StageIssue::InsideWall { what, x, y } => {
+                write!(f, "{} at (x: {}, y: {}) is inside a wall", what, x, y)
+            }
+            StageIssue::Floating { what, x, y } => {
+                write!(f, "{} at (x: {}, y: {}) has no floor under it", what, x, y)
+            }
+            StageIssue::GoalUnreachable => {
+                write!(f, "goal is not reachable from the player start")
+            }
+        }
+    }
+}
+
+// pathの拡張子でステージ定義ファイルかTiledのマップかを判断して検査する
+pub fn validate_stage(bytes: &[u8], path: &Path) -> Vec<StageIssue> {
+    let stage = if is_tiled_map(path) {
+        stage_from_tiled(bytes, path).map_err(|error| vec![StageIssue::Load(error.to_string())])
+    } else {
+        load_stage_file(bytes)
+    };
+    match stage {
+        Ok(stage) => validate_stage_data(&stage),
+        Err(issues) => issues,
+    }
+}
+
+// ツール用。ファイルを読んで検査する（assets/で始まるパスはassetsからのパスとして扱う）
+pub fn validate_stage_at(path: &Path) -> Vec<StageIssue> {
+    match std::fs::read(path) {
+        Ok(bytes) => validate_stage(&bytes, asset_path(path)),
+        Err(error) => vec![StageIssue::Load(error.to_string())],
+    }
+}
+
+fn load_stage_file(bytes: &[u8]) -> Result<StageData, Vec<StageIssue>> {
+    let to_issue = |error: StageLoadError| StageIssue::Load(error.to_string());
+    let file = StageFile::from_bytes(bytes).map_err(|error| vec![to_issue(error)])?;
+    let errors = map_errors(&file.legend, &file.map);
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(to_issue).collect());
+    }
+    StageData::from_file(file).map_err(|error| vec![to_issue(error)])
+}
+
+// 読み込めたステージの配置とゴールまでの道を調べる
+pub fn validate_stage_data(stage: &StageData) -> Vec<StageIssue> {
+    let map = CollisionMap::from_stage(stage, TILE_SIZE);
+    let size = Vec2::new(CHARACTER_SIZE, CHARACTER_SIZE);
+    let center = |x: u32, y: u32| {
+        Vec3::new(
+            TILE_SIZE * x as f32,
+            TILE_SIZE * stage.row_from_bottom(y) as f32,
+            0.,
+        )
+    };
+
+    let mut issues = Vec::new();
+    let start = stage.player_start;
+    if map.overlaps_wall(center(start.x, start.y), size) {
+        issues.push(StageIssue::InsideWall {
+            what: "player start".to_string(),
+            x: start.x,
+            y: start.y,
+        });
+    }
+
+    let mut enemies: Vec<(String, &EnemyPosition)> = stage
+        .enemies
+        .iter()
+        .enumerate()
+        .map(|(i, enemy)| (format!("enemy #{}", i), enemy))
+        .collect();
+    for (i, trigger) in stage.triggers.iter().enumerate() {
+        for action in trigger.actions.iter() {
+            if let TriggerAction::SpawnEnemies(spawns) = action {
+                enemies.extend(
+                    spawns
+                        .iter()
+                        .enumerate()
+                        .map(|(j, enemy)| (format!("trigger #{} enemy #{}", i, j), enemy)),
+                );
+            }
+        }
+    }
+    for (what, enemy) in enemies {
+        let position = center(enemy.x, enemy.y);
+        if map.overlaps_wall(position, size) {
+            issues.push(StageIssue::InsideWall {
+                what,
+                x: enemy.x,
+                y: enemy.y,
+            });
+        } else if enemy.kind != EnemyKind::RedDemon
+            && !map.is_on_floor(position, size)
+            && !on_platform(stage, enemy)
+        {
+            // 飛ぶ敵(RedDemon)以外は床の上に置く
+            issues.push(StageIssue::Floating {
+                what,
+                x: enemy.x,
+                y: enemy.y,
+            });
+        }
+    }
+
+    if !Reachability::new(stage).is_goal_reachable() {
+        issues.push(StageIssue::GoalUnreachable);
+    }
+    issues
+}
+
+// 動く足場（最初の位置）か崩れる足場の上に乗っているか
+fn on_platform(stage: &StageData, enemy: &EnemyPosition) -> bool {
+    let moving = stage
+        .moving_platforms
+        .iter()
+        .filter_map(|platform| platform.path.first().map(|first| (*first, platform.width)));
+    let crumbling = stage.crumbling_platforms.iter().map(|platform| {
+        (
+            TilePosition {
+                x: platform.x,
+                y: platform.y,
+            },
+            platform.width,
+        )
+    });
+    moving.chain(crumbling).any(|(position, width)| {
+        position.y == enemy.y + 1 && (position.x..position.x + width.max(1)).contains(&enemy.x)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage::test_stage_source;
+
+    fn issues(source: &str) -> Vec<String> {
+        validate_stage(source.as_bytes(), Path::new("test.stage.ron"))
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_map_error() {
+        let source = test_stage_source(
+            "player_start: (x: 1, y: 1), enemies: [],",
+            &["#....", "#..x.", "#...", "#####"],
+        );
+        assert_eq!(
+            issues(&source),
+            vec![
+                "row 1, column 3: tile 'x' is not defined in legend",
+                "row 2: expected 5 tiles but found 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_misplaced_characters() {
+        let source = test_stage_source(
+            "player_start: (x: 0, y: 2),
+    enemies: [
+        (x: 5, y: 2, kind: Slime),
+        (x: 2, y: 0, kind: Lizard),
+        (x: 3, y: 0, kind: RedDemon),
+        (x: 5, y: 1, kind: Wizard),
+    ],
+    goal: (x: 6, y: 2),",
+            &["........", "........", "#.#..#..", "########"],
+        );
+        assert_eq!(
+            issues(&source),
+            vec![
+                "player start at (x: 0, y: 2) is inside a wall",
+                "enemy #0 at (x: 5, y: 2) is inside a wall",
+                "enemy #1 at (x: 2, y: 0) has no floor under it",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_goal() {
+        let source = test_stage_source(
+            "player_start: (x: 1, y: 3), enemies: [], goal: (x: 8, y: 3),",
+            &[
+                "#.........",
+                "#....#....",
+                "#....#....",
+                "#....#....",
+                "#....#....",
+                "##########",
+            ],
+        );
+        assert_eq!(
+            issues(&source),
+            vec!["goal is not reachable from the player start"]
+        );
+    }
+}